@@ -0,0 +1,608 @@
+//! Caching agent, which keeps decrypted keys in memory for a while and
+//! computes passwords for the CLI, so that not every `compute` runs gpg.
+//!
+//! The agent listens on a Unix socket in a directory that only the user can
+//! access and answers only peers of the same user. Each connection carries
+//! one request and one response, both a line of JSON:
+//!
+//! ```json
+//! {"compute":{"store_dir":"/home/alice/.totpc","gnupg_home":"/home/alice/.gnupg","identifier":"foo","time":1111111109,"next":false}}
+//! {"totp":{"totp":"081804","valid_for":1,"next_totp":null}}
+//! {"error":{"message":"no entry found for foo","exit_code":4}}
+//! ```
+//!
+//! Keys are kept in locked memory, which is overwritten when they expire
+//! after the TTL or when their key file changes. Core dumps of the agent are
+//! disabled.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fs::{metadata, remove_file, set_permissions, symlink_metadata, DirBuilder, Permissions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute::{compute, seconds_remaining, time_step, Algorithm},
+    error::Error,
+    file::key_file_path,
+    store::Store,
+};
+
+/// Environment variable to set the socket of the agent.
+pub const ENV_AGENT_SOCKET: &str = "TOTPC_AGENT_SOCKET";
+/// Seconds that the agent keeps a decrypted key by default.
+pub const DEFAULT_AGENT_TTL: u64 = 300;
+const SOCKET_DIR_NAME: &str = "totpc";
+const SOCKET_FILE_NAME: &str = "agent.sock";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time the CLI waits for a response, which includes entering the passphrase
+/// if the agent has to decrypt the key.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+const PURGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Request of the CLI to the agent.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    /// Compute the password of the identifier in the store at the given time.
+    Compute {
+        store_dir: PathBuf,
+        gnupg_home: PathBuf,
+        identifier: String,
+        time: u64,
+        next: bool,
+    },
+}
+
+/// Response of the agent to a request.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Totp {
+        totp: String,
+        valid_for: u64,
+        next_totp: Option<String>,
+    },
+    /// Error of the request, with the exit code it maps to.
+    Error { message: String, exit_code: i32 },
+}
+
+/// Password, seconds it remains valid and password of the next time step if
+/// requested.
+pub type Codes = (String, u64, Option<String>);
+
+/// Socket of the agent in the given runtime directory, e. g.
+/// `$XDG_RUNTIME_DIR`, or else in a directory of the user in the temporary
+/// directory.
+pub fn default_socket_path(runtime_dir: Option<PathBuf>) -> PathBuf {
+    runtime_dir
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join(SOCKET_DIR_NAME))
+        .unwrap_or_else(|| {
+            // SAFETY: getuid cannot fail.
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir().join(format!("{SOCKET_DIR_NAME}-{uid}"))
+        })
+        .join(SOCKET_FILE_NAME)
+}
+
+/// Ask the agent at the given socket for the password of the identifier in
+/// the store. Returns `None` if no agent is running or it does not respond in
+/// time, so that the key is decrypted directly instead.
+pub fn request_codes(
+    socket: &Path,
+    store_dir: &Path,
+    gnupg_home: &Path,
+    identifier: &str,
+    time: u64,
+    next: bool,
+) -> Option<Result<Codes, Error>> {
+    let request = Request::Compute {
+        store_dir: store_dir.to_path_buf(),
+        gnupg_home: gnupg_home.to_path_buf(),
+        identifier: identifier.to_string(),
+        time,
+        next,
+    };
+    Some(
+        request_agent(socket, &request, RESPONSE_TIMEOUT)?.and_then(|response| match response {
+            Response::Totp {
+                totp,
+                valid_for,
+                next_totp,
+            } => Ok((totp, valid_for, next_totp)),
+            Response::Error { message, exit_code } => Err(Error::Agent { message, exit_code }),
+        }),
+    )
+}
+
+/// Response of the agent at the given socket to the request, or `None` if no
+/// agent is running or it does not respond within the timeout.
+fn request_agent(
+    socket: &Path,
+    request: &Request,
+    timeout: Duration,
+) -> Option<Result<Response, Error>> {
+    let stream = UnixStream::connect(socket).ok()?;
+    // a socket of another user is not the agent, but may have been planted
+    check_peer(&stream).ok()?;
+    match exchange(stream, request, timeout) {
+        Err(Error::Io { source, .. })
+            if matches!(source.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+            None
+        }
+        result => Some(result),
+    }
+}
+
+fn exchange(
+    mut stream: UnixStream,
+    request: &Request,
+    timeout: Duration,
+) -> Result<Response, Error> {
+    stream
+        .set_write_timeout(Some(REQUEST_TIMEOUT))
+        .and_then(|()| stream.set_read_timeout(Some(timeout)))
+        .map_err(|err| Error::io("error setting agent timeout", err))?;
+    let mut line = serde_json::to_string(request)
+        .map_err(|error| Error::Other(format!("error serializing agent request - {error}")))?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|err| Error::io("error sending request to agent", err))?;
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .map_err(|err| Error::io("error receiving response of agent", err))?;
+    serde_json::from_str(&response)
+        .map_err(|error| Error::Other(format!("invalid response of agent - {error}")))
+}
+
+/// Decrypted key with its parameters, kept until it expires.
+struct CachedKey {
+    key: LockedBytes,
+    algorithm: Algorithm,
+    digits: u32,
+    period: u64,
+    /// Modification time of the key file when it was decrypted.
+    modified: SystemTime,
+    expires: Instant,
+}
+
+type Cache = Arc<Mutex<HashMap<(PathBuf, PathBuf), CachedKey>>>;
+
+/// Run the agent on the given socket, keeping decrypted keys for the given
+/// duration. Only returns on errors of the socket.
+pub fn serve(socket: &Path, ttl: Duration) -> Result<Infallible, Error> {
+    disable_core_dumps();
+    let listener = bind(socket)?;
+    let cache = Cache::default();
+    let purged_cache = Arc::clone(&cache);
+    thread::spawn(move || loop {
+        thread::sleep(PURGE_INTERVAL);
+        let now = Instant::now();
+        lock(&purged_cache).retain(|_, cached| cached.expires > now);
+    });
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .map_err(|err| Error::io("error accepting agent connection", err))?;
+        let cache = Arc::clone(&cache);
+        // Connections are handled concurrently, as decrypting may wait for
+        // pinentry. A failing connection only concerns its client.
+        thread::spawn(move || handle(stream, &cache, ttl));
+    }
+}
+
+/// Bind the socket, creating its directory so that only the user can access
+/// it, and replace the socket of an agent that is no longer running. Fails if
+/// the directory exists and others may access it.
+fn bind(socket: &Path) -> Result<UnixListener, Error> {
+    if let Some(dir) = socket.parent() {
+        if !dir.exists() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|err| {
+                    Error::io(format!("error creating agent dir {}", dir.display()), err)
+                })?;
+        }
+        check_socket_dir(dir)?;
+    }
+    if UnixStream::connect(socket).is_ok() {
+        return Err(Error::Other(format!(
+            "agent is already running on {}",
+            socket.display()
+        )));
+    }
+    match remove_file(socket) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(Error::io(
+                format!("error removing stale agent socket {}", socket.display()),
+                err,
+            ))
+        }
+        _ => {}
+    }
+    UnixListener::bind(socket)
+        .and_then(|listener| {
+            set_permissions(socket, Permissions::from_mode(0o600))?;
+            Ok(listener)
+        })
+        .map_err(|err| {
+            Error::io(
+                format!("error binding agent socket {}", socket.display()),
+                err,
+            )
+        })
+}
+
+fn handle(stream: UnixStream, cache: &Cache, ttl: Duration) -> Result<(), Error> {
+    check_peer(&stream)?;
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(|err| Error::io("error setting agent timeout", err))?;
+    let mut line = String::new();
+    let mut reader = BufReader::new(stream);
+    reader
+        .read_line(&mut line)
+        .map_err(|err| Error::io("error receiving agent request", err))?;
+    let response = match serde_json::from_str(&line)
+        .map_err(|error| Error::InvalidArgument(format!("invalid agent request - {error}")))
+        .and_then(|request| answer(request, cache, ttl))
+    {
+        Ok(response) => response,
+        Err(error) => Response::Error {
+            message: error.to_string(),
+            exit_code: error.exit_code(),
+        },
+    };
+    let mut line = serde_json::to_string(&response)
+        .map_err(|error| Error::Other(format!("error serializing agent response - {error}")))?;
+    line.push('\n');
+    reader
+        .into_inner()
+        .write_all(line.as_bytes())
+        .map_err(|err| Error::io("error sending agent response", err))
+}
+
+fn answer(request: Request, cache: &Cache, ttl: Duration) -> Result<Response, Error> {
+    let Request::Compute {
+        store_dir,
+        gnupg_home,
+        identifier,
+        time,
+        next,
+    } = request;
+    let key_file = key_file_path(&store_dir, &identifier)?;
+    let modified = metadata(&key_file)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => Error::EntryNotFound(identifier.clone()),
+            _ => Error::io(format!("error reading {}", key_file.display()), err),
+        })?;
+    let cache_key = (gnupg_home.clone(), key_file);
+    if !lock(cache)
+        .get(&cache_key)
+        .is_some_and(|cached| cached.modified == modified && cached.expires > Instant::now())
+    {
+        // Decrypt without holding the lock, gpg may wait for pinentry.
+        let mut entry = Store::open(&store_dir, &gnupg_home)?.get(&identifier)?;
+        let key = LockedBytes::new(entry.key()?);
+        // SAFETY: zeros are valid UTF-8.
+        wipe(unsafe { entry.secret.as_bytes_mut() });
+        lock(cache).insert(
+            cache_key.clone(),
+            CachedKey {
+                key: key?,
+                algorithm: entry.algorithm,
+                digits: entry.digits,
+                period: entry.period,
+                modified,
+                expires: Instant::now() + ttl,
+            },
+        );
+    }
+    let cache = lock(cache);
+    let Some(cached) = cache.get(&cache_key) else {
+        return Err(Error::Other("key expired while decrypting".to_string()));
+    };
+    let code_at = |time: u64| {
+        compute(
+            &cached.key.0,
+            time_step(time, cached.period),
+            cached.algorithm,
+            cached.digits,
+        )
+    };
+    Ok(Response::Totp {
+        totp: code_at(time)?,
+        valid_for: seconds_remaining(time, cached.period),
+        next_totp: if next {
+            Some(code_at(time.saturating_add(cached.period))?)
+        } else {
+            None
+        },
+    })
+}
+
+fn lock(cache: &Cache) -> MutexGuard<'_, HashMap<(PathBuf, PathBuf), CachedKey>> {
+    // Entries stay consistent even if a holder of the lock panicked.
+    cache.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Fail unless the peer of the socket runs as the same user.
+/// Check that the directory of the socket is no symbolic link, is owned by
+/// the user and only the user can access it, e. g. the directory in the
+/// temporary directory, which another user may have created first.
+fn check_socket_dir(dir: &Path) -> Result<(), Error> {
+    let metadata = symlink_metadata(dir)
+        .map_err(|err| Error::io(format!("error reading agent dir {}", dir.display()), err))?;
+    // SAFETY: getuid cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let mode = metadata.mode() & 0o777;
+    if !metadata.is_dir() || metadata.uid() != uid || mode != 0o700 {
+        return Err(Error::Other(format!(
+            "agent dir {} must be a directory of user {uid} with mode 700, not {} with mode {mode:o}",
+            dir.display(),
+            metadata.uid()
+        )));
+    }
+    Ok(())
+}
+
+fn check_peer(stream: &UnixStream) -> Result<(), Error> {
+    let peer_uid = peer_uid(stream).map_err(|err| Error::io("error checking agent peer", err))?;
+    // SAFETY: getuid cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if peer_uid != uid {
+        return Err(Error::Other(format!(
+            "agent peer runs as user {peer_uid}, not as {uid}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: credentials and length are valid for writes of their size.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            ptr::addr_of_mut!(credentials).cast(),
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: uid and gid are valid for writes.
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Keep keys out of core dumps and, on Linux, away from debuggers of the
+/// same user.
+fn disable_core_dumps() {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: limit is a valid rlimit. Failing only leaves core dumps enabled.
+    unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) };
+    #[cfg(target_os = "linux")]
+    // SAFETY: PR_SET_DUMPABLE takes no pointers.
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0)
+    };
+}
+
+/// Bytes in memory that is locked against swapping and overwritten when
+/// dropped.
+struct LockedBytes(Box<[u8]>);
+
+impl LockedBytes {
+    /// Move the given bytes to locked memory, overwriting the original.
+    fn new(mut bytes: Vec<u8>) -> Result<Self, Error> {
+        let mut locked = vec![0; bytes.len()].into_boxed_slice();
+        // SAFETY: the range is the allocation of locked.
+        if unsafe { libc::mlock(locked.as_ptr().cast(), locked.len()) } != 0 {
+            wipe(&mut bytes);
+            return Err(Error::io(
+                "error locking memory - raise the limit of locked memory (ulimit -l)",
+                io::Error::last_os_error(),
+            ));
+        }
+        locked.copy_from_slice(&bytes);
+        wipe(&mut bytes);
+        Ok(Self(locked))
+    }
+}
+
+impl Drop for LockedBytes {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+        // SAFETY: the range is the allocation locked in new.
+        unsafe { libc::munlock(self.0.as_ptr().cast(), self.0.len()) };
+    }
+}
+
+/// Overwrite the bytes with zeros, without the writes being optimized away.
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes {
+        // SAFETY: byte is a valid reference.
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir, set_permissions, Permissions},
+        os::unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::{Path, PathBuf},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use tempfile::tempdir;
+
+    use crate::{
+        agent::{
+            bind, default_socket_path, exchange, request_agent, request_codes, serve, LockedBytes,
+            Request, REQUEST_TIMEOUT, RESPONSE_TIMEOUT,
+        },
+        Error,
+    };
+
+    #[test]
+    fn bind_refuses_dir_that_others_may_access() {
+        let dir = tempdir().unwrap();
+        let socket_dir = dir.path().join("totpc");
+        create_dir(&socket_dir).unwrap();
+        set_permissions(&socket_dir, Permissions::from_mode(0o755)).unwrap();
+
+        assert!(bind(&socket_dir.join("agent.sock")).is_err());
+
+        set_permissions(&socket_dir, Permissions::from_mode(0o700)).unwrap();
+        assert!(bind(&socket_dir.join("agent.sock")).is_ok());
+    }
+
+    #[test]
+    fn socket_is_in_runtime_dir() {
+        assert_eq!(
+            default_socket_path(Some(PathBuf::from("/run/user/1000"))),
+            Path::new("/run/user/1000/totpc/agent.sock")
+        );
+        assert!(
+            default_socket_path(Some(PathBuf::from("relative"))).starts_with(std::env::temp_dir())
+        );
+    }
+
+    #[test]
+    fn request_without_agent_returns_none() {
+        let dir = tempdir().unwrap();
+
+        let codes = request_codes(
+            &dir.path().join("agent.sock"),
+            dir.path(),
+            dir.path(),
+            "foo",
+            0,
+            false,
+        );
+
+        assert!(codes.is_none());
+    }
+
+    #[test]
+    fn agent_reports_errors_with_exit_codes() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent").join("agent.sock");
+        let serve_socket = socket.clone();
+        thread::spawn(move || serve(&serve_socket, Duration::from_secs(1)));
+        while UnixStream::connect(&socket).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let result = request_codes(&socket, dir.path(), dir.path(), "missing", 0, false);
+        assert!(matches!(
+            result,
+            Some(Err(Error::Agent { exit_code: 4, .. }))
+        ));
+        let result = request_codes(&socket, dir.path(), dir.path(), "../escape", 0, false);
+        assert!(matches!(
+            result,
+            Some(Err(Error::Agent { exit_code: 10, .. }))
+        ));
+
+        let stream = UnixStream::connect(&socket).unwrap();
+        let request = Request::Compute {
+            store_dir: dir.path().to_path_buf(),
+            gnupg_home: dir.path().to_path_buf(),
+            identifier: "missing".to_string(),
+            time: 0,
+            next: true,
+        };
+        assert!(exchange(stream, &request, RESPONSE_TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn agent_answers_while_other_connection_waits() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent").join("agent.sock");
+        let serve_socket = socket.clone();
+        thread::spawn(move || serve(&serve_socket, Duration::from_secs(1)));
+        while UnixStream::connect(&socket).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // sends no request, so the agent waits for it
+        let _waiting = UnixStream::connect(&socket).unwrap();
+        let start = Instant::now();
+
+        let result = request_codes(&socket, dir.path(), dir.path(), "missing", 0, false);
+
+        assert!(matches!(result, Some(Err(Error::Agent { .. }))));
+        assert!(start.elapsed() < REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn request_to_unresponsive_agent_returns_none() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let _listener = UnixListener::bind(&socket).unwrap();
+        let request = Request::Compute {
+            store_dir: dir.path().to_path_buf(),
+            gnupg_home: dir.path().to_path_buf(),
+            identifier: "foo".to_string(),
+            time: 0,
+            next: false,
+        };
+
+        let response = request_agent(&socket, &request, Duration::from_millis(10));
+
+        assert!(response.is_none());
+    }
+
+    #[test]
+    fn locked_bytes_keep_content() {
+        let locked = LockedBytes::new(b"key".to_vec()).unwrap();
+
+        assert_eq!(&*locked.0, b"key");
+    }
+}
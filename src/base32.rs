@@ -1,7 +1,14 @@
 use base32::Alphabet;
 
+use crate::error::Error;
+
 /// Decode Base32 encoded key to UTF-8 string.
-pub fn decode(key: &str) -> Result<Vec<u8>, String> {
+pub fn decode(key: &str) -> Result<Vec<u8>, Error> {
     let maybe_key_bytes = base32::decode(Alphabet::RFC4648 { padding: false }, key);
-    maybe_key_bytes.ok_or_else(|| "Error: invalid key encoding (must be Base32)".to_string())
+    maybe_key_bytes.ok_or_else(|| Error::InvalidKey("encoding must be Base32".to_string()))
+}
+
+/// Encode key bytes to Base32 without padding.
+pub fn encode(key: &[u8]) -> String {
+    base32::encode(Alphabet::RFC4648 { padding: false }, key)
 }
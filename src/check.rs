@@ -0,0 +1,375 @@
+//! Health check of a store: its GPG id, its key files and the files around
+//! them.
+
+use std::{
+    ffi::OsStr,
+    fs::{read_dir, Metadata},
+    path::Path,
+};
+
+use crate::{
+    entry::Entry,
+    error::Error,
+    error_reason,
+    file::{
+        encrypted_key_ids, read_decrypted_key_from_file, read_gpg_id, recipient_key_ids, Warning,
+        GPG_FILE_EXTENSION, GPG_ID_FILE_NAME, INDEX_FILE_NAME,
+    },
+    git::GIT_DIR_NAME,
+    index::read_index,
+};
+
+/// Files that belong in a store besides the key files and the index.
+const KNOWN_FILES: [&str; 3] = [GPG_ID_FILE_NAME, ".gitignore", ".gitattributes"];
+
+/// Key id of recipients that the sender hid.
+const HIDDEN_KEY_ID: &str = "0000000000000000";
+
+/// Check the store directory at the given time. Returns the number of key
+/// files checked and the problems found: a missing GPG id or recipient key,
+/// key files that are empty, fail to decrypt, hold no valid entry or are
+/// encrypted for other keys than the ones of the GPG id of their folder, an
+/// unreadable index, stray files, empty folders and files that others may
+/// write.
+pub fn check_store(
+    gnupg_home: &Path,
+    store_dir: &Path,
+    time: u64,
+) -> Result<(usize, Vec<Warning>), Error> {
+    let mut check = Check {
+        gnupg_home,
+        store_dir,
+        time,
+        checked: 0,
+        problems: vec![],
+    };
+    let gpg_id_file = store_dir.join(GPG_ID_FILE_NAME);
+    let key_ids = match read_gpg_id(store_dir) {
+        Err(Error::NotInitialized(_)) => {
+            check.report(&gpg_id_file, "missing - run init with a gpg id");
+            None
+        }
+        Err(error) => {
+            check.report(&gpg_id_file, error_reason(&error));
+            None
+        }
+        Ok(_) => check.recipient_key_ids(store_dir)?,
+    };
+    check.check_folder(store_dir, "", key_ids.as_deref())?;
+    Ok((check.checked, check.problems))
+}
+
+/// State of a store check.
+struct Check<'a> {
+    gnupg_home: &'a Path,
+    store_dir: &'a Path,
+    time: u64,
+    checked: usize,
+    problems: Vec<Warning>,
+}
+
+impl Check<'_> {
+    /// Check the files in the folder and its subfolders, whose identifiers
+    /// start with the given prefix. Key files are expected to be encrypted for
+    /// the given key ids unless the folder has a GPG id of its own.
+    fn check_folder(
+        &mut self,
+        dir: &Path,
+        prefix: &str,
+        key_ids: Option<&[String]>,
+    ) -> Result<(), Error> {
+        let files = match read_dir(dir) {
+            Ok(files) => files,
+            Err(err) => {
+                self.report(dir, format!("cannot read folder - {err}"));
+                return Ok(());
+            }
+        };
+        if let Ok(metadata) = dir.metadata() {
+            self.check_permissions(dir, &metadata);
+        }
+        let is_store_dir = dir == self.store_dir;
+        let folder_key_ids;
+        let key_ids = match is_store_dir || !dir.join(GPG_ID_FILE_NAME).is_file() {
+            true => key_ids,
+            false => {
+                folder_key_ids = self.recipient_key_ids(dir)?;
+                folder_key_ids.as_deref()
+            }
+        };
+
+        let mut paths = vec![];
+        for file in files {
+            match file {
+                Ok(file) => paths.push(file.path()),
+                Err(err) => self.report(dir, format!("cannot read folder - {err}")),
+            }
+        }
+        if paths.is_empty() && !is_store_dir {
+            self.report(dir, "empty folder");
+        }
+        paths.sort();
+        let key_file_suffix = format!(".{GPG_FILE_EXTENSION}");
+        for path in paths {
+            let Some(file_name) = path.file_name().and_then(OsStr::to_str) else {
+                self.report(&path, "file name is not valid UTF-8");
+                continue;
+            };
+            let metadata = match path.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    self.report(&path, format!("cannot read file - {err}"));
+                    continue;
+                }
+            };
+            if metadata.is_dir() {
+                // symbolic links to folders are not listed either
+                if file_name.starts_with('.') || path.is_symlink() {
+                    if !(is_store_dir && file_name == GIT_DIR_NAME) {
+                        self.report(&path, "stray directory");
+                    }
+                } else {
+                    self.check_folder(&path, &format!("{prefix}{file_name}/"), key_ids)?;
+                }
+                continue;
+            }
+            self.check_permissions(&path, &metadata);
+            if KNOWN_FILES.contains(&file_name) {
+                continue;
+            }
+            if is_store_dir && file_name == INDEX_FILE_NAME {
+                if let Err(error) = read_index(self.gnupg_home, self.store_dir, true) {
+                    let message = format!("{} - rebuild it with totpc index", error_reason(&error));
+                    self.report(&path, message);
+                }
+                continue;
+            }
+            let Some(name) = file_name
+                .strip_suffix(&key_file_suffix)
+                .filter(|name| !name.starts_with('.'))
+            else {
+                self.report(&path, "stray file, not a key file");
+                continue;
+            };
+            self.checked += 1;
+            if metadata.len() == 0 {
+                self.report(&path, "empty key file");
+                continue;
+            }
+            if let Err(error) = self.check_key(&format!("{prefix}{name}")) {
+                self.report(&path, error_reason(&error));
+            }
+            if let Some(key_ids) = key_ids {
+                self.check_recipients(&path, key_ids);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ids of the keys of the GPG id of the folder, or `None` if it has
+    /// none or the keyring has no key for it, which is reported.
+    fn recipient_key_ids(&mut self, dir: &Path) -> Result<Option<Vec<String>>, Error> {
+        let gpg_id_file = dir.join(GPG_ID_FILE_NAME);
+        let gpg_id = match read_gpg_id(dir) {
+            Ok(gpg_id) => gpg_id,
+            Err(error) => {
+                self.report(&gpg_id_file, error_reason(&error));
+                return Ok(None);
+            }
+        };
+        if gpg_id.is_empty() {
+            self.report(&gpg_id_file, "empty - run init with a gpg id");
+            return Ok(None);
+        }
+        let key_ids = recipient_key_ids(self.gnupg_home, &gpg_id)?;
+        if key_ids.is_empty() {
+            let message = format!("no key for gpg id {gpg_id} in the keyring");
+            self.report(&gpg_id_file, message);
+            return Ok(None);
+        }
+        Ok(Some(key_ids))
+    }
+
+    /// Decrypt the key file of the identifier, parse its entry and compute a
+    /// password.
+    fn check_key(&self, identifier: &str) -> Result<(), Error> {
+        let payload =
+            read_decrypted_key_from_file(self.gnupg_home, self.store_dir, identifier, true)?
+                .ok_or_else(|| Error::EntryNotFound(identifier.to_string()))?;
+        Entry::parse(&payload)?.totp()?.generate_at(self.time)?;
+        Ok(())
+    }
+
+    /// Report the key file if it is encrypted for other keys than the given
+    /// ones.
+    fn check_recipients(&mut self, path: &Path, key_ids: &[String]) {
+        // files that gpg cannot parse fail to decrypt already
+        let other_key_ids = encrypted_key_ids(self.gnupg_home, path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|key_id| key_id != HIDDEN_KEY_ID && !key_ids.contains(key_id))
+            .collect::<Vec<_>>();
+        if !other_key_ids.is_empty() {
+            let message = format!(
+                "encrypted for keys other than the gpg id: {}",
+                other_key_ids.join(", ")
+            );
+            self.report(path, message);
+        }
+    }
+
+    /// Report the file if users other than its owner may write it, which
+    /// would let them replace keys.
+    #[cfg(unix)]
+    fn check_permissions(&mut self, path: &Path, metadata: &Metadata) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = metadata.permissions().mode();
+        if mode & 0o022 != 0 {
+            let message = format!(
+                "writable by group or others (mode {:o}) - run chmod go-w",
+                mode & 0o777
+            );
+            self.report(path, message);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn check_permissions(&mut self, _path: &Path, _metadata: &Metadata) {}
+
+    fn report(&mut self, path: &Path, message: impl Into<String>) {
+        self.problems.push(Warning {
+            path: Some(path.to_path_buf()),
+            message: message.into(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, write};
+
+    use tempfile::TempDir;
+
+    use crate::{
+        check::check_store,
+        file::{
+            init, tests::generate_temp_gpg_key_pair_without_passphrase,
+            write_encrypted_key_to_file, Warning,
+        },
+    };
+
+    /// Problems other than permissions, which depend on the umask, as
+    /// `<file name>: <message>`.
+    fn messages(problems: &[Warning]) -> Vec<String> {
+        problems
+            .iter()
+            .filter(|problem| !problem.message.starts_with("writable"))
+            .map(|problem| {
+                let file_name = problem.path.as_ref().unwrap().file_name().unwrap();
+                format!("{}: {}", file_name.to_string_lossy(), problem.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_reports_stray_and_empty_files() {
+        let dir = TempDir::new().unwrap();
+        let store_dir = dir.path().join("store");
+        create_dir(&store_dir).unwrap();
+        write(store_dir.join("empty.gpg"), "").unwrap();
+        write(store_dir.join("notes.txt"), "stray").unwrap();
+        create_dir(store_dir.join("sub")).unwrap();
+        create_dir(store_dir.join(".hidden")).unwrap();
+
+        let (checked, problems) = check_store(dir.path(), &store_dir, 1111111109).unwrap();
+
+        assert_eq!(checked, 1);
+        assert_eq!(
+            messages(&problems),
+            [
+                ".gpg-id: missing - run init with a gpg id",
+                ".hidden: stray directory",
+                "empty.gpg: empty key file",
+                "notes.txt: stray file, not a key file",
+                "sub: empty folder",
+            ]
+        );
+        init(&store_dir, "").unwrap();
+        let (_, problems) = check_store(dir.path(), &store_dir, 1111111109).unwrap();
+        assert!(problems
+            .iter()
+            .any(|problem| problem.message == "empty - run init with a gpg id"));
+    }
+
+    #[test]
+    fn check_reports_keys_that_fail_to_decrypt_or_are_invalid() {
+        let dir = TempDir::new().unwrap();
+        let store_dir = dir.path().join("store");
+        generate_temp_gpg_key_pair_without_passphrase(dir.path(), "Test Man");
+        init(&store_dir, "Test Man").unwrap();
+        write_encrypted_key_to_file(dir.path(), &store_dir, "valid", "JBSWY3DPEHPK3PXP").unwrap();
+        write_encrypted_key_to_file(dir.path(), &store_dir, "invalid", "NOT-BASE32!").unwrap();
+        write(store_dir.join("broken.gpg"), "not encrypted").unwrap();
+
+        let (checked, problems) = check_store(dir.path(), &store_dir, 1111111109).unwrap();
+
+        let messages = messages(&problems);
+        assert_eq!(checked, 3);
+        assert_eq!(messages.len(), 2, "{messages:?}");
+        // the reason is the last line of the error output of gpg
+        assert!(
+            messages[0].starts_with("broken.gpg: gpg: ") && messages[0].contains("failed"),
+            "{messages:?}"
+        );
+        assert_eq!(
+            messages[1],
+            "invalid.gpg: invalid key - encoding must be Base32"
+        );
+    }
+
+    #[test]
+    fn check_reports_keys_encrypted_for_other_key() {
+        let dir = TempDir::new().unwrap();
+        let store_dir = dir.path().join("store");
+        generate_temp_gpg_key_pair_without_passphrase(dir.path(), "Test Man");
+        generate_temp_gpg_key_pair_without_passphrase(dir.path(), "Other Man");
+        init(&store_dir, "Other Man").unwrap();
+        write_encrypted_key_to_file(dir.path(), &store_dir, "other", "JBSWY3DPEHPK3PXP").unwrap();
+        write(store_dir.join(".gpg-id"), "Test Man").unwrap();
+        write_encrypted_key_to_file(dir.path(), &store_dir, "own", "JBSWY3DPEHPK3PXP").unwrap();
+
+        let (checked, problems) = check_store(dir.path(), &store_dir, 1111111109).unwrap();
+
+        let messages = messages(&problems);
+        assert_eq!(checked, 2);
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        assert!(messages[0].starts_with("other.gpg: encrypted for keys other than the gpg id: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_reports_files_writable_by_others() {
+        use std::{
+            fs::{set_permissions, Permissions},
+            os::unix::fs::PermissionsExt,
+        };
+
+        let dir = TempDir::new().unwrap();
+        let store_dir = dir.path().join("store");
+        create_dir(&store_dir).unwrap();
+        set_permissions(&store_dir, Permissions::from_mode(0o700)).unwrap();
+        let notes = store_dir.join(".gitignore");
+        write(&notes, "").unwrap();
+        set_permissions(&notes, Permissions::from_mode(0o666)).unwrap();
+
+        let (_, problems) = check_store(dir.path(), &store_dir, 1111111109).unwrap();
+
+        assert!(problems
+            .iter()
+            .any(|problem| problem.path.as_deref() == Some(notes.as_path())
+                && problem
+                    .message
+                    .starts_with("writable by group or others (mode 666)")));
+    }
+}
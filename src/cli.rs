@@ -0,0 +1,487 @@
+use std::path::PathBuf;
+
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    Parser, Subcommand,
+};
+
+use crate::{
+    completion::{Shell, COMMAND_COMPLETE},
+    compute::{Algorithm, MAX_VERIFY_WINDOW},
+    generate::DEFAULT_KEY_BITS,
+    output::Format,
+    time::{parse_offset, parse_time},
+    COMMAND_AGENT, COMMAND_CHECK, COMMAND_COMPLETIONS, COMMAND_COMPUTE, COMMAND_COPY,
+    COMMAND_DELETE, COMMAND_EDIT, COMMAND_FIND, COMMAND_GENERATE, COMMAND_INDEX, COMMAND_INIT,
+    COMMAND_LIST, COMMAND_LOAD, COMMAND_MENU, COMMAND_MOVE, COMMAND_NATIVE_HOST, COMMAND_SAVE,
+    COMMAND_SHORT_COMPUTE, COMMAND_SHORT_EDIT, COMMAND_SHORT_GENERATE, COMMAND_SHORT_INIT,
+    COMMAND_SHORT_LIST, COMMAND_SHORT_LOAD, COMMAND_SHORT_SAVE, COMMAND_SHORT_VERIFY, COMMAND_TAG,
+    COMMAND_TUI, COMMAND_VERIFY,
+};
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0   success
+  1   I/O or other error
+  2   invalid usage or config
+  3   not initialized
+  4   entry not found
+  5   entry exists
+  6   decryption failed
+  7   decryption cancelled
+  8   encryption failed
+  9   invalid key
+  10  invalid identifier
+  11  gpg not installed
+  12  code did not verify
+  13  store check found problems
+  14  computing some entries failed";
+
+/// TOTP Computer - time-based one time password computer
+#[derive(Debug, Parser)]
+#[command(name = "totpc", version, after_help = EXIT_CODES_HELP)]
+pub struct Cli {
+    /// Output results as human readable text, JSON or the raw value only,
+    /// e. g. just the password.
+    /// [default: format of the config file or text]
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<Format>,
+
+    /// Directory of the key store [default: $TOTPC_STORE_DIR, $TOTPC_DIR,
+    /// config file, $HOME/.totpc if it exists or $XDG_DATA_HOME/totpc]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub store_dir: Option<PathBuf>,
+
+    /// Home directory of GnuPG [default: $GNUPGHOME, config file or
+    /// $HOME/.gnupg]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub gnupg_home: Option<PathBuf>,
+
+    /// Config file [default: $TOTPC_CONFIG or
+    /// $XDG_CONFIG_HOME/totpc/config.toml]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Profile of the config file to use [default: $TOTPC_PROFILE or
+    /// default_profile of the config file]
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Command to run, lists all identifiers if omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands of totpc.
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum Command {
+    /// Initialize totp computer with gpg id for encrypting keys.
+    #[command(name = COMMAND_INIT, visible_alias = COMMAND_SHORT_INIT)]
+    Init {
+        /// GPG id to encrypt keys for.
+        gpg_id: String,
+
+        /// Folder of the store whose keys are encrypted for the gpg id
+        /// instead, e. g. `team`. Keys moved or copied into it are
+        /// re-encrypted.
+        #[arg(long, value_name = "FOLDER")]
+        folder: Option<String>,
+    },
+
+    /// List all stored identifiers.
+    #[command(name = COMMAND_LIST, visible_alias = COMMAND_SHORT_LIST)]
+    List {
+        /// Only list identifiers whose entries have the tag. Repeat to require
+        /// several tags.
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Also show issuer, account, tags, algorithm and last use of the
+        /// entries.
+        #[arg(short, long)]
+        long: bool,
+    },
+
+    /// Find identifiers that contain the pattern or its characters in order,
+    /// ignoring case, best matches first.
+    #[command(name = COMMAND_FIND)]
+    Find {
+        /// Pattern to search for, e. g. "aws" or "gthb".
+        pattern: String,
+
+        /// Also match the issuer and account of the entries, which decrypts
+        /// all of them.
+        #[arg(long)]
+        metadata: bool,
+    },
+
+    /// Compute current one time password for given identifier and show the
+    /// seconds it remains valid. An identifier that does not exist may be an
+    /// unambiguous prefix, part or fuzzy match of one.
+    #[command(name = COMMAND_COMPUTE, visible_alias = COMMAND_SHORT_COMPUTE)]
+    Compute {
+        /// Identifier of the key.
+        #[arg(required_unless_present_any = ["all", "tags"])]
+        identifier: Option<String>,
+
+        /// Compute the passwords of all stored identifiers and output them as
+        /// a table.
+        #[arg(long, conflicts_with = "identifier")]
+        all: bool,
+
+        /// Compute the passwords of the identifiers whose entries have the
+        /// tag and output them as a table. Repeat to require several tags.
+        #[arg(long = "tag", value_name = "TAG", conflicts_with = "identifier")]
+        tags: Vec<String>,
+
+        /// Also output the password of the next time step.
+        #[arg(long)]
+        next: bool,
+
+        /// Compute the password at the given Unix time or RFC 3339 timestamp
+        /// instead of the current time.
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        at: Option<u64>,
+
+        /// Shift the time by the given number of seconds, e. g. +30 or -90.
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            allow_hyphen_values = true,
+            value_parser = parse_offset,
+            default_value_t = 0
+        )]
+        offset: i64,
+    },
+
+    /// Verify code against the current one time password of given identifier
+    /// and output the offset of the matching time step.
+    #[command(name = COMMAND_VERIFY, visible_alias = COMMAND_SHORT_VERIFY)]
+    Verify {
+        /// Identifier of the key.
+        identifier: String,
+
+        /// Code to verify.
+        code: String,
+
+        /// Number of time steps checked before and after the current one, at
+        /// most 10.
+        #[arg(
+            long,
+            value_name = "STEPS",
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u64).range(..=MAX_VERIFY_WINDOW)
+        )]
+        window: u64,
+    },
+
+    /// Generate a random key for given identifier, store it and output the
+    /// otpauth URI for provisioning.
+    #[command(name = COMMAND_GENERATE, visible_alias = COMMAND_SHORT_GENERATE)]
+    Generate {
+        /// Identifier of the key.
+        identifier: String,
+
+        /// Length of the key in bits.
+        #[arg(
+            long,
+            default_value_t = DEFAULT_KEY_BITS,
+            value_parser = PossibleValuesParser::new(["160", "256", "512"])
+                .map(|bits| bits.parse::<usize>().unwrap_or_default())
+        )]
+        bits: usize,
+
+        /// Hash algorithm.
+        #[arg(long, value_enum, ignore_case = true, default_value_t = Algorithm::Sha1)]
+        algorithm: Algorithm,
+
+        /// Number of digits of the passwords [default: digits of the config
+        /// file or 6]
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(["6", "7", "8"])
+                .map(|digits| digits.parse::<u32>().unwrap_or_default())
+        )]
+        digits: Option<u32>,
+
+        /// Length of a time step in seconds [default: period of the config
+        /// file or 30]
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        period: Option<u64>,
+
+        /// Provider of the account, shown in authenticator apps.
+        #[arg(long)]
+        issuer: Option<String>,
+
+        /// Name of the account at the issuer, shown in authenticator apps.
+        #[arg(long)]
+        account: Option<String>,
+
+        /// Web address of the login page, matched by the native messaging
+        /// host.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Tag of the entry, e. g. "prod". Repeat to add several tags.
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Also output the otpauth URI as QR code.
+        #[arg(long)]
+        qr: bool,
+    },
+
+    /// Delete identifier and key from store.
+    #[command(name = COMMAND_DELETE)]
+    Delete {
+        /// Identifier of the key.
+        identifier: String,
+    },
+
+    /// Edit key of given identifier in $VISUAL or $EDITOR. The decrypted key
+    /// is only written to a temporary file on tmpfs if available, which is
+    /// overwritten and deleted afterwards.
+    #[command(name = COMMAND_EDIT, visible_alias = COMMAND_SHORT_EDIT)]
+    Edit {
+        /// Identifier of the key.
+        identifier: String,
+    },
+
+    /// Add or remove tags of given identifier, which group entries for
+    /// `list --tag` and `compute --tag`. Commits the change if the store is a
+    /// git repository.
+    #[command(name = COMMAND_TAG)]
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+
+    /// Rebuild the encrypted index of every store from its entries, which
+    /// decrypts all of them. The index holds the metadata that `list --long`,
+    /// `list --tag`, `find --metadata` and completion show without decrypting
+    /// every entry, and is updated whenever entries change once it exists.
+    #[command(name = COMMAND_INDEX)]
+    Index,
+
+    /// Check the health of every store: that its gpg id has a key in the
+    /// keyring, that every key file decrypts for it, holds a valid entry and
+    /// computes a password, and that there are no stray or empty files or
+    /// files that others may write. Decrypts all entries and exits with code
+    /// 13 if it finds problems.
+    #[command(name = COMMAND_CHECK)]
+    Check,
+
+    /// Rename identifier, or move it to another folder or store. Re-encrypts
+    /// the key if the gpg ids differ and commits the change if the store is a
+    /// git repository.
+    #[command(name = COMMAND_MOVE, visible_alias = "rename")]
+    Move {
+        /// Identifier of the key.
+        source: String,
+
+        /// New identifier of the key.
+        target: String,
+
+        /// Overwrite the key of an existing target identifier.
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Copy key of identifier to another identifier, possibly in another
+    /// folder or store. Re-encrypts the key if the gpg ids differ and commits
+    /// the change if the store is a git repository.
+    #[command(name = COMMAND_COPY)]
+    Copy {
+        /// Identifier of the key.
+        source: String,
+
+        /// Identifier of the copy.
+        target: String,
+
+        /// Overwrite the key of an existing target identifier.
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Decrypt and output key of given identifier.
+    #[command(name = COMMAND_LOAD, visible_alias = COMMAND_SHORT_LOAD)]
+    Read {
+        /// Identifier of the key.
+        identifier: String,
+    },
+
+    /// Save key for given identifier. Prompts to overwrite existing files.
+    #[command(name = COMMAND_SAVE, visible_alias = COMMAND_SHORT_SAVE)]
+    Store {
+        /// Identifier of the key.
+        identifier: String,
+    },
+
+    /// Choose an identifier with a picker like dmenu, rofi, wofi or fzf and
+    /// copy its current password to the clipboard or type it, e. g. bound to
+    /// a hotkey. Commands are set in the config file or default to the ones of
+    /// the display server.
+    #[command(name = COMMAND_MENU)]
+    Menu {
+        /// Type the password into the focused window instead of copying it.
+        #[arg(long = "type")]
+        type_code: bool,
+
+        /// Picker command, reading identifiers as lines and outputting the
+        /// chosen one [default: picker of the config file, wofi --dmenu on
+        /// Wayland, dmenu on X11 or fzf]
+        #[arg(long, value_name = "COMMAND")]
+        picker: Option<String>,
+    },
+
+    /// Show the live passwords of all identifiers in a full-screen terminal
+    /// interface, grouped by store and folder. Type to filter, press enter to
+    /// copy the password of the selected identifier and escape to quit.
+    #[command(name = COMMAND_TUI)]
+    Tui,
+
+    /// Run the caching agent, which keeps decrypted keys in locked memory and
+    /// computes passwords while it runs, so that `compute` does not decrypt
+    /// again, e. g. with `totpc agent &`. Listens on $TOTPC_AGENT_SOCKET or
+    /// $XDG_RUNTIME_DIR/totpc/agent.sock.
+    #[command(name = COMMAND_AGENT)]
+    Agent {
+        /// Seconds to keep a decrypted key [default: agent_ttl of the config
+        /// file or 300]
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        ttl: Option<u64>,
+    },
+
+    /// Serve a browser extension with the native messaging protocol on stdin
+    /// and stdout, answering the current passwords of the entries whose url
+    /// is on the host of the origin of a page.
+    #[command(name = COMMAND_NATIVE_HOST)]
+    NativeHost {
+        /// Arguments passed by the browser, e. g. the origin of the
+        /// extension, which are ignored.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+        browser_args: Vec<String>,
+    },
+
+    /// Output completion script for the given shell, e. g. for
+    /// `source <(totpc completions bash)`.
+    #[command(name = COMMAND_COMPLETIONS)]
+    Completions {
+        /// Shell to complete in.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Output completion candidates for the given command line words.
+    #[command(name = COMMAND_COMPLETE, hide = true)]
+    Complete {
+        /// Command line words without the program name, the last one being
+        /// completed.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+}
+
+/// Changes of the tags of an entry.
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum TagAction {
+    /// Add tags to the entry of given identifier.
+    Add {
+        /// Identifier of the key.
+        identifier: String,
+
+        /// Tags to add, e. g. "prod" or "aws".
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Remove tags from the entry of given identifier.
+    Remove {
+        /// Identifier of the key.
+        identifier: String,
+
+        /// Tags to remove.
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use crate::{
+        cli::{Cli, Command, TagAction},
+        output::Format,
+    };
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_compute_options() {
+        let cli = Cli::try_parse_from([
+            "totpc",
+            "c",
+            "test_identifier",
+            "--at",
+            "2005-03-18T01:58:29Z",
+            "--offset",
+            "-30",
+            "--next",
+            "--format",
+            "json",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.format, Some(Format::Json));
+        assert_eq!(
+            cli.command,
+            Some(Command::Compute {
+                identifier: Some("test_identifier".to_string()),
+                all: false,
+                tags: vec![],
+                next: true,
+                at: Some(1111111109),
+                offset: -30
+            })
+        );
+    }
+
+    #[test]
+    fn parse_tag_options() {
+        let cli = Cli::try_parse_from(["totpc", "tag", "add", "aws-root", "prod", "aws"]).unwrap();
+
+        assert_eq!(
+            cli.command,
+            Some(Command::Tag {
+                action: TagAction::Add {
+                    identifier: "aws-root".to_string(),
+                    tags: vec!["prod".to_string(), "aws".to_string()],
+                }
+            })
+        );
+        assert!(Cli::try_parse_from(["totpc", "tag", "remove", "aws-root"]).is_err());
+        assert!(Cli::try_parse_from(["totpc", "compute", "--tag", "prod"]).is_ok());
+        assert!(Cli::try_parse_from(["totpc", "compute", "aws-root", "--tag", "prod"]).is_err());
+    }
+
+    #[test]
+    fn parse_verify_window_up_to_maximum() {
+        let verify =
+            |window: &str| Cli::try_parse_from(["totpc", "verify", "id", "0", "--window", window]);
+
+        assert!(verify("10").is_ok());
+        for window in ["11", "-1", "9223372036854775808", "18446744073709551615"] {
+            assert!(verify(window).is_err(), "{window}");
+        }
+    }
+
+    #[test]
+    fn parse_fails_on_unknown_option() {
+        let result = Cli::try_parse_from(["totpc", "compute", "test_identifier", "--unknown"]);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    iter::once,
+    path::{Path, PathBuf},
+};
+
+use clap::{Arg, CommandFactory, ValueEnum};
+
+use crate::{
+    cli::Cli,
+    config::{Config, ENV_PROFILE},
+    dirs::{Dirs, STORE_SEPARATOR},
+    file::list_identifiers,
+    index::{read_index, IndexEntry},
+};
+
+/// Name of the hidden command that outputs completion candidates.
+pub const COMMAND_COMPLETE: &str = "__complete";
+const ARG_IDENTIFIERS: [&str; 3] = ["identifier", "source", "target"];
+const ARG_CONFIG: &str = "config";
+const ARG_GNUPG_HOME: &str = "gnupg_home";
+const ARG_PROFILE: &str = "profile";
+const ARG_STORE_DIR: &str = "store_dir";
+const ARGS_DIRS: [&str; 4] = [ARG_CONFIG, ARG_GNUPG_HOME, ARG_PROFILE, ARG_STORE_DIR];
+const ARG_TAGS: &str = "tags";
+
+/// Shells for which completion scripts can be generated.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(name = "powershell")]
+    PowerShell,
+}
+
+/// Completion script for the given shell. The script calls the hidden
+/// `__complete` command, which derives candidates from the command
+/// definitions and the identifiers in the store.
+pub fn script(shell: Shell) -> String {
+    let script = match shell {
+        Shell::Bash => BASH_SCRIPT,
+        Shell::Zsh => ZSH_SCRIPT,
+        Shell::Fish => FISH_SCRIPT,
+        Shell::PowerShell => POWERSHELL_SCRIPT,
+    };
+    script
+        .trim_end()
+        .replace("{bin}", Cli::command().get_name())
+        .replace("{complete}", COMMAND_COMPLETE)
+}
+
+/// Completion candidates for the given command line words, without the
+/// program name. The last word is the one being completed. Every candidate is
+/// a value and a description, which may be empty.
+///
+/// Identifiers are read from the store directory given with `--store-dir` on
+/// the command line, or from the store directory of `dirs` otherwise, and
+/// from the additional stores of `dirs`, qualified with their names. If the
+/// command line has `--config`, `--profile` or `--gnupg-home`, the
+/// directories are resolved again from them instead of taken from `dirs`.
+pub fn complete(words: &[String], dirs: &Dirs) -> Vec<(String, String)> {
+    let mut command = Cli::command();
+    command.build();
+    let (current_word, preceding_words) = match words.split_last() {
+        None => ("", words),
+        Some((current_word, preceding_words)) => (current_word.as_str(), preceding_words),
+    };
+
+    let mut command = &command;
+    let mut positional_index = 0;
+    let mut pending_option: Option<&Arg> = None;
+    let mut dir_options = BTreeMap::new();
+    for word in preceding_words {
+        if let Some(option) = pending_option.take() {
+            if ARGS_DIRS.contains(&option.get_id().as_str()) {
+                dir_options.insert(option.get_id().as_str(), word.clone());
+            }
+        } else if let Some(long) = word.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                None => (long, None),
+                Some((name, value)) => (name, Some(value)),
+            };
+            let option = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(name));
+            if let Some(option) = option {
+                match value {
+                    None if option.get_action().takes_values() => pending_option = Some(option),
+                    Some(value) if ARGS_DIRS.contains(&option.get_id().as_str()) => {
+                        dir_options.insert(option.get_id().as_str(), value.to_string());
+                    }
+                    _ => {}
+                }
+            }
+        } else if word.starts_with('-') {
+            // short flags do not take values
+        } else if command.has_subcommands() {
+            match command.find_subcommand(word) {
+                None => return vec![],
+                Some(subcommand) => command = subcommand,
+            }
+        } else {
+            positional_index += 1;
+        }
+    }
+    let resolved_dirs = resolve_dirs(&dir_options);
+    let dirs = resolved_dirs.as_ref().unwrap_or(dirs);
+    let store_dir = dir_options
+        .get(ARG_STORE_DIR)
+        .map_or_else(|| dirs.store_dir.clone(), PathBuf::from);
+
+    let candidates = if let Some(option) = pending_option {
+        match option.get_id() == ARG_TAGS {
+            true => tag_candidates(dirs, &store_dir),
+            false => possible_values(option),
+        }
+    } else if current_word.starts_with('-') {
+        command
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(|arg| {
+                arg.get_long()
+                    .map(|long| (format!("--{long}"), help_line(arg.get_help())))
+            })
+            .collect()
+    } else if command.has_subcommands() {
+        command
+            .get_subcommands()
+            .filter(|subcommand| !subcommand.is_hide_set())
+            .map(|subcommand| {
+                (
+                    subcommand.get_name().to_string(),
+                    help_line(subcommand.get_about()),
+                )
+            })
+            .collect()
+    } else {
+        match command.get_positionals().nth(positional_index) {
+            None => vec![],
+            Some(arg) if ARG_IDENTIFIERS.contains(&arg.get_id().as_str()) => {
+                identifier_candidates(dirs, &store_dir)
+            }
+            Some(arg) if arg.get_id() == ARG_TAGS => tag_candidates(dirs, &store_dir),
+            Some(arg) => possible_values(arg),
+        }
+    };
+    candidates
+        .into_iter()
+        .filter(|(value, _)| value.starts_with(current_word))
+        .collect()
+}
+
+/// Directories resolved from the config file, profile and GnuPG home given on
+/// the command line like on startup, or `None` if none is given or they cannot
+/// be resolved, as completion does not report errors.
+fn resolve_dirs(dir_options: &BTreeMap<&str, String>) -> Option<Dirs> {
+    if ![ARG_CONFIG, ARG_GNUPG_HOME, ARG_PROFILE]
+        .iter()
+        .any(|id| dir_options.contains_key(id))
+    {
+        return None;
+    }
+    let env_var = |name: &str| env::var_os(name);
+    let profile = dir_options.get(ARG_PROFILE).cloned().or_else(|| {
+        env::var(ENV_PROFILE)
+            .ok()
+            .filter(|profile| !profile.is_empty())
+    });
+    let settings = Config::load(dir_options.get(ARG_CONFIG).map(PathBuf::from), env_var)
+        .and_then(|config| config.settings(profile.as_deref()))
+        .ok()?;
+    Dirs::resolve(
+        dir_options.get(ARG_STORE_DIR).map(PathBuf::from),
+        dir_options.get(ARG_GNUPG_HOME).map(PathBuf::from),
+        &settings,
+        env_var,
+    )
+    .ok()
+}
+
+/// Prefixes and directories of the given store directory and the additional
+/// stores of `dirs`.
+fn stores(dirs: &Dirs, store_dir: &Path) -> Vec<(String, PathBuf)> {
+    once((String::new(), store_dir.to_path_buf()))
+        .chain(
+            dirs.stores
+                .iter()
+                .map(|(name, store_dir)| (format!("{name}{STORE_SEPARATOR}"), store_dir.clone())),
+        )
+        .collect()
+}
+
+/// Entries of the index of the store directory, if GnuPG can decrypt it
+/// without asking for the passphrase, as completion must not prompt.
+fn indexed_entries(dirs: &Dirs, store_dir: &Path) -> BTreeMap<String, IndexEntry> {
+    read_index(&dirs.gnupg_home, store_dir, false)
+        .ok()
+        .flatten()
+        .map(|index| index.entries)
+        .unwrap_or_default()
+}
+
+/// Identifiers of all stores, described by issuer and account if indexed.
+fn identifier_candidates(dirs: &Dirs, store_dir: &Path) -> Vec<(String, String)> {
+    let mut candidates = vec![];
+    for (prefix, store_dir) in stores(dirs, store_dir) {
+        let Ok(listing) = list_identifiers(&store_dir) else {
+            continue;
+        };
+        let indexed = indexed_entries(dirs, &store_dir);
+        candidates.extend(listing.identifiers.into_iter().map(|identifier| {
+            let description = indexed
+                .get(&identifier)
+                .map(|indexed| {
+                    [&indexed.issuer, &indexed.account]
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            (format!("{prefix}{identifier}"), description)
+        }));
+    }
+    candidates
+}
+
+/// Tags of the indexed entries of all stores.
+fn tag_candidates(dirs: &Dirs, store_dir: &Path) -> Vec<(String, String)> {
+    stores(dirs, store_dir)
+        .into_iter()
+        .flat_map(|(_, store_dir)| indexed_entries(dirs, &store_dir).into_values())
+        .flat_map(|indexed| indexed.tags)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|tag| (tag, String::new()))
+        .collect()
+}
+
+fn possible_values(arg: &Arg) -> Vec<(String, String)> {
+    arg.get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| (value.get_name().to_string(), help_line(value.get_help())))
+        .collect()
+}
+
+fn help_line(help: Option<&clap::builder::StyledStr>) -> String {
+    help.map(|help| {
+        help.to_string()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+const BASH_SCRIPT: &str = r#"_{bin}() {
+    # split the line on whitespace only, as bash also breaks words at ':'
+    local line="${COMP_LINE:0:COMP_POINT}" IFS=$' \t\n'
+    local -a words
+    read -ra words <<< "$line"
+    [[ $line == *[[:space:]] ]] && words+=("")
+    local current="${words[${#words[@]}-1]}"
+    IFS=$'\n'
+    COMPREPLY=($({bin} {complete} -- "${words[@]:1}" 2>/dev/null | cut -f1))
+    if [[ $current == *:* && $COMP_WORDBREAKS == *:* ]]; then
+        COMPREPLY=("${COMPREPLY[@]#"${current%:*}:"}")
+    fi
+}
+
+complete -o bashdefault -o default -F _{bin} {bin}
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef {bin}
+
+_{bin}() {
+    local -a candidates
+    local line
+    for line in "${(@f)$({bin} {complete} -- "${(@)words[2,CURRENT]}" 2>/dev/null)}"; do
+        [[ -n $line ]] || continue
+        candidates+=("${${line%%$'\t'*}//:/\\:}:${line#*$'\t'}")
+    done
+    if (( ${#candidates} )); then
+        _describe {bin} candidates
+    else
+        _files
+    fi
+}
+
+compdef _{bin} {bin}
+"#;
+
+const FISH_SCRIPT: &str = r#"function __{bin}_complete
+    set -l tokens (commandline -opc)
+    set -e tokens[1]
+    {bin} {complete} -- $tokens (commandline -ct | string collect --allow-empty) 2>/dev/null
+end
+
+complete -c {bin} -f -a '(__{bin}_complete)'
+"#;
+
+const POWERSHELL_SCRIPT: &str = r#"Register-ArgumentCompleter -Native -CommandName {bin} -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object { $_.ToString() })
+    if ($wordToComplete -eq '') {
+        $words += ''
+    }
+    {bin} {complete} -- @words 2>$null | ForEach-Object {
+        $value, $description = $_ -split "`t", 2
+        if (-not $description) {
+            $description = $value
+        }
+        [System.Management.Automation.CompletionResult]::new($value, $value, 'ParameterValue', $description)
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::write, path::Path};
+
+    use tempfile::TempDir;
+
+    use crate::{completion::complete, dirs::Dirs};
+
+    fn values(words: &[&str], store_dir: &Path) -> Vec<String> {
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+        complete(&words, &Dirs::new(store_dir, "/nonexistent"))
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    #[test]
+    fn complete_commands() {
+        let store_dir = Path::new("/nonexistent");
+
+        assert_eq!(values(&["ver"], store_dir), vec!["verify"]);
+        assert!(values(&[""], store_dir).contains(&"help".to_string()));
+        assert!(!values(&[""], store_dir)
+            .iter()
+            .any(|value| value.starts_with("__")));
+    }
+
+    #[test]
+    fn complete_options_and_values() {
+        let store_dir = Path::new("/nonexistent");
+
+        assert_eq!(values(&["c", "--ne"], store_dir), vec!["--next"]);
+        assert_eq!(
+            values(&["--format", ""], store_dir),
+            vec!["text", "json", "raw"]
+        );
+    }
+
+    #[test]
+    fn complete_identifiers_from_store_dir_option() {
+        let store_dir = TempDir::new().unwrap();
+        write(store_dir.path().join("test_id_1.gpg"), "").unwrap();
+        write(store_dir.path().join("other.gpg"), "").unwrap();
+        let store_dir_option = store_dir.path().to_str().unwrap();
+
+        assert_eq!(
+            values(
+                &["--store-dir", store_dir_option, "compute", "te"],
+                Path::new("/nonexistent")
+            ),
+            vec!["test_id_1"]
+        );
+        assert_eq!(
+            values(&["r", ""], store_dir.path()),
+            vec!["other", "test_id_1"]
+        );
+        assert!(values(&["r", "other", ""], store_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn complete_qualified_identifiers_of_additional_stores() {
+        let store_dir = TempDir::new().unwrap();
+        write(store_dir.path().join("aws-root.gpg"), "").unwrap();
+        let mut dirs = Dirs::new("/nonexistent", "/nonexistent");
+        dirs.stores
+            .insert("team".to_string(), store_dir.path().to_path_buf());
+        let words = ["c".to_string(), "team:".to_string()];
+
+        let candidates = complete(&words, &dirs);
+
+        assert_eq!(
+            candidates,
+            vec![("team:aws-root".to_string(), String::new())]
+        );
+    }
+
+    #[test]
+    fn complete_identifiers_of_store_of_profile_option() {
+        let dir = TempDir::new().unwrap();
+        let work_dir = dir.path().join("work");
+        std::fs::create_dir(&work_dir).unwrap();
+        write(work_dir.join("aws-root.gpg"), "").unwrap();
+        let config_file = dir.path().join("config.toml");
+        write(
+            &config_file,
+            format!(
+                "[profiles.work]\nstore_dir = {:?}\ngnupg_home = \"/nonexistent\"\n",
+                work_dir.to_str().unwrap()
+            ),
+        )
+        .unwrap();
+        let config_option = config_file.to_str().unwrap();
+
+        assert_eq!(
+            values(
+                &["--config", config_option, "--profile=work", "c", ""],
+                Path::new("/nonexistent")
+            ),
+            vec!["aws-root"]
+        );
+        assert!(values(
+            &["--config", config_option, "--profile", "unknown", "c", ""],
+            Path::new("/nonexistent")
+        )
+        .is_empty());
+    }
+}
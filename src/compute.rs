@@ -1,19 +1,78 @@
-use hmac::{Hmac, Mac};
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use serde::{Deserialize, Serialize};
 use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::error::Error;
+
+/// Default length of a time step in seconds.
+pub const TIME_STEP_INTERVAL: u64 = 30;
+/// Default number of digits of a TOTP.
+pub const DEFAULT_DIGITS: u32 = 6;
+/// Supported numbers of digits of a TOTP.
+pub const DIGITS: [u32; 3] = [6, 7, 8];
+/// Largest number of time steps verified before and after the given one. Wider
+/// windows would accept almost any code.
+pub const MAX_VERIFY_WINDOW: u64 = 10;
 
-/// Compute a time-based one time password according to RFC 2468 from given
-/// plain text key and time step.
-pub fn compute(key: &[u8], time_step: u64) -> Result<String, String> {
+/// Hash algorithm of the HMAC that a TOTP is computed with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, ValueEnum)]
+pub enum Algorithm {
+    #[default]
+    #[serde(rename = "SHA1")]
+    #[value(name = "SHA1")]
+    Sha1,
+    #[serde(rename = "SHA256")]
+    #[value(name = "SHA256")]
+    Sha256,
+    #[serde(rename = "SHA512")]
+    #[value(name = "SHA512")]
+    Sha512,
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sha1 => f.write_str("SHA1"),
+            Self::Sha256 => f.write_str("SHA256"),
+            Self::Sha512 => f.write_str("SHA512"),
+        }
+    }
+}
+
+/// Time step of the given length in seconds that the given Unix time in
+/// seconds falls into.
+pub fn time_step(time: u64, period: u64) -> u64 {
+    time / period
+}
+
+/// Seconds until the time step of the given length in seconds of the given
+/// Unix time in seconds ends.
+pub fn seconds_remaining(time: u64, period: u64) -> u64 {
+    period - time % period
+}
+
+/// Compute a time-based one time password with the given number of digits
+/// according to RFC 6238 from given plain text key and time step.
+pub fn compute(
+    key: &[u8],
+    time_step: u64,
+    algorithm: Algorithm,
+    digits: u32,
+) -> Result<String, Error> {
     let time_step_bytes = time_step.to_be_bytes();
 
-    let mut mac = Hmac::<Sha1>::new_from_slice(key)
-        .map_err(|error| format!("Error: invalid key length - {error}"))?;
-    mac.update(&time_step_bytes);
-    let result = mac.finalize();
-    let code_bytes = result.into_bytes();
+    let code_bytes = match algorithm {
+        Algorithm::Sha1 => mac::<Hmac<Sha1>>(key, &time_step_bytes)?,
+        Algorithm::Sha256 => mac::<Hmac<Sha256>>(key, &time_step_bytes)?,
+        Algorithm::Sha512 => mac::<Hmac<Sha512>>(key, &time_step_bytes)?,
+    };
 
     let last_byte = code_bytes.last().ok_or_else(|| {
-        "Error: could not compute TOTP (MAC does not contain any bytes)".to_string()
+        Error::Other("could not compute TOTP (MAC does not contain any bytes)".to_string())
     })?;
     let offset = last_byte & 0xf;
     let offset = offset as usize;
@@ -24,7 +83,7 @@ pub fn compute(key: &[u8], time_step: u64) -> Result<String, String> {
         | (code_bytes[offset + 3] as u32 & 0xff);
 
     let base: u32 = 10;
-    let totp_length = 6;
+    let totp_length = digits;
     let modulo_operator = base.pow(totp_length);
     let totp = dynamic_binary_code % modulo_operator;
     let totp_length = totp_length as usize;
@@ -33,16 +92,77 @@ pub fn compute(key: &[u8], time_step: u64) -> Result<String, String> {
     Ok(totp_digits)
 }
 
+/// Verify a TOTP against the given time step and `window` time steps before
+/// and after it. Returns the offset of the matching time step, preferring the
+/// offset closest to the given time step.
+///
+/// All time steps in the window are computed and compared in constant time, so
+/// the duration does not reveal which step matched. Fails for windows wider
+/// than [`MAX_VERIFY_WINDOW`].
+pub fn verify(
+    key: &[u8],
+    time_step: u64,
+    code: &str,
+    window: u64,
+    algorithm: Algorithm,
+    digits: u32,
+) -> Result<Option<i64>, Error> {
+    if window > MAX_VERIFY_WINDOW {
+        return Err(Error::InvalidArgument(format!(
+            "window must be at most {MAX_VERIFY_WINDOW} time steps"
+        )));
+    }
+    let window = window as i64;
+    let mut matching_offset = None;
+    for offset in -window..=window {
+        let Some(step) = time_step.checked_add_signed(offset) else {
+            continue;
+        };
+        let totp = compute(key, step, algorithm, digits)?;
+        if constant_time_eq(totp.as_bytes(), code.as_bytes())
+            && matching_offset.is_none_or(|matched: i64| offset.abs() < matched.abs())
+        {
+            matching_offset = Some(offset);
+        }
+    }
+    Ok(matching_offset)
+}
+
+/// HMAC of the given message.
+fn mac<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut mac = <M as Mac>::new_from_slice(key)
+        .map_err(|error| Error::InvalidKey(format!("invalid length - {error}")))?;
+    mac.update(message);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Compare two byte slices in time independent of their content.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b)
+        .fold(0, |difference, (x, y)| difference | (x ^ y))
+        == 0
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::compute::compute;
+    use crate::{
+        compute::{
+            compute, seconds_remaining, time_step, verify, Algorithm, MAX_VERIFY_WINDOW,
+            TIME_STEP_INTERVAL,
+        },
+        Error,
+    };
 
     #[test]
     fn test_encode_step_1_1() {
         let key = Vec::<u8>::from("12345678901234567890");
         let time_step = 1;
 
-        let totp = compute(&key, time_step).unwrap();
+        let totp = compute(&key, time_step, Algorithm::Sha1, 6).unwrap();
 
         assert_eq!(totp, "287082".to_string());
     }
@@ -52,11 +172,66 @@ mod tests {
         let key = Vec::from("12345678901234567890");
         let time_step = 37037036;
 
-        let totp = compute(&key, time_step).unwrap();
+        let totp = compute(&key, time_step, Algorithm::Sha1, 6).unwrap();
 
         assert_eq!(totp, "081804".to_string());
     }
 
+    #[test]
+    fn test_encode_step_1_rfc_6238_test_vectors() {
+        let time_step = 1;
+
+        let totp = compute(b"12345678901234567890", time_step, Algorithm::Sha1, 8).unwrap();
+        assert_eq!(totp, "94287082");
+
+        let key = b"12345678901234567890123456789012";
+        let totp = compute(key, time_step, Algorithm::Sha256, 8).unwrap();
+        assert_eq!(totp, "46119246");
+
+        let key = b"1234567890123456789012345678901234567890123456789012345678901234";
+        let totp = compute(key, time_step, Algorithm::Sha512, 8).unwrap();
+        assert_eq!(totp, "90693936");
+    }
+
+    #[test]
+    fn time_step_and_seconds_remaining() {
+        assert_eq!(time_step(59, TIME_STEP_INTERVAL), 1);
+        assert_eq!(seconds_remaining(59, TIME_STEP_INTERVAL), 1);
+        assert_eq!(time_step(60, TIME_STEP_INTERVAL), 2);
+        assert_eq!(
+            seconds_remaining(60, TIME_STEP_INTERVAL),
+            TIME_STEP_INTERVAL
+        );
+        assert_eq!(time_step(60, 60), 1);
+        assert_eq!(seconds_remaining(59, 60), 1);
+    }
+
+    #[test]
+    fn verify_matches_within_window() {
+        let key = Vec::from("12345678901234567890");
+        let time_step = 37037036;
+        let verify = |time_step, code, window| {
+            verify(&key, time_step, code, window, Algorithm::Sha1, 6).unwrap()
+        };
+
+        assert_eq!(verify(time_step, "081804", 0), Some(0));
+        assert_eq!(verify(time_step + 2, "081804", 1), None);
+        assert_eq!(verify(time_step + 2, "081804", 2), Some(-2));
+        assert_eq!(verify(time_step - 1, "081804", 1), Some(1));
+        assert_eq!(verify(time_step, "000000", 1), None);
+    }
+
+    #[test]
+    fn verify_fails_for_too_wide_window() {
+        let key = Vec::from("12345678901234567890");
+        let verify = |window| verify(&key, 37037036, "081804", window, Algorithm::Sha1, 6);
+
+        assert_eq!(verify(MAX_VERIFY_WINDOW).unwrap(), Some(0));
+        for window in [MAX_VERIFY_WINDOW + 1, u64::MAX] {
+            assert!(matches!(verify(window), Err(Error::InvalidArgument(_))));
+        }
+    }
+
     // #[test]
     // fn test_encode_step_1_37037037() {
     //     let key = b"12345678901234567890";
@@ -0,0 +1,337 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{dirs::STORE_SEPARATOR, error::Error, output::Format};
+
+/// Environment variable to set the config file.
+pub const ENV_CONFIG: &str = "TOTPC_CONFIG";
+/// Environment variable to select a profile.
+pub const ENV_PROFILE: &str = "TOTPC_PROFILE";
+const ENV_HOME: &str = "HOME";
+const ENV_XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
+const CONFIG_DIR_NAME: &str = "totpc";
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Settings of the config file, at the top level or in a profile.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Directory of the key store.
+    pub store_dir: Option<PathBuf>,
+    /// Home directory of GnuPG.
+    pub gnupg_home: Option<PathBuf>,
+    /// Output format.
+    pub format: Option<Format>,
+    /// Seconds after which a copied code is cleared from the clipboard.
+    pub clipboard_timeout: Option<u64>,
+    /// Number of digits of new entries.
+    pub digits: Option<u32>,
+    /// Time step interval in seconds of new entries.
+    pub period: Option<u64>,
+    /// Command of `menu` to choose an identifier, e. g. "rofi -dmenu".
+    pub picker: Option<String>,
+    /// Command of `menu` to copy a code to the clipboard, e. g. "wl-copy".
+    pub clipboard: Option<String>,
+    /// Command of `menu --type` to type a code, e. g. "wtype -".
+    pub typer: Option<String>,
+    /// Command to output the clipboard, to clear it only if it still holds
+    /// the copied code, e. g. "wl-paste".
+    pub paste: Option<String>,
+    /// Seconds that the agent keeps a decrypted key.
+    pub agent_ttl: Option<u64>,
+    /// Whether `compute` and `menu` record the time an entry was last used in
+    /// the index of its store, which encrypts the index again every time.
+    pub record_last_used: Option<bool>,
+    /// Additional stores by name, addressed as `<name>:<identifier>`.
+    #[serde(default)]
+    pub stores: BTreeMap<String, PathBuf>,
+}
+
+impl Settings {
+    /// Settings of `self`, overridden by the ones set in `other`.
+    fn merge(&self, other: &Settings) -> Settings {
+        Settings {
+            store_dir: other.store_dir.clone().or_else(|| self.store_dir.clone()),
+            gnupg_home: other.gnupg_home.clone().or_else(|| self.gnupg_home.clone()),
+            format: other.format.or(self.format),
+            clipboard_timeout: other.clipboard_timeout.or(self.clipboard_timeout),
+            digits: other.digits.or(self.digits),
+            period: other.period.or(self.period),
+            picker: other.picker.clone().or_else(|| self.picker.clone()),
+            clipboard: other.clipboard.clone().or_else(|| self.clipboard.clone()),
+            typer: other.typer.clone().or_else(|| self.typer.clone()),
+            paste: other.paste.clone().or_else(|| self.paste.clone()),
+            agent_ttl: other.agent_ttl.or(self.agent_ttl),
+            record_last_used: other.record_last_used.or(self.record_last_used),
+            stores: self
+                .stores
+                .iter()
+                .chain(&other.stores)
+                .map(|(name, dir)| (name.clone(), dir.clone()))
+                .collect(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(digits) = self.digits {
+            if !(6..=8).contains(&digits) {
+                return Err(format!("digits must be 6, 7 or 8, not {digits}"));
+            }
+        }
+        if self.period == Some(0) {
+            return Err("period must be greater than 0".to_string());
+        }
+        if self.agent_ttl == Some(0) {
+            return Err("agent_ttl must be greater than 0".to_string());
+        }
+        if let Some(name) = self
+            .stores
+            .keys()
+            .find(|name| name.is_empty() || name.contains([STORE_SEPARATOR, '/', '\\']))
+        {
+            return Err(format!("invalid store name \"{name}\""));
+        }
+        Ok(())
+    }
+}
+
+/// Contents of the config file.
+///
+/// ```toml
+/// default_profile = "personal"
+/// format = "text"
+///
+/// [stores]
+/// team = "/mnt/team/totpc"
+///
+/// [profiles.personal]
+/// store_dir = "~/.totpc"
+///
+/// [profiles.work]
+/// store_dir = "~/work/totpc"
+/// gnupg_home = "~/work/gnupg"
+/// ```
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Config {
+    /// Profile to use when none is selected.
+    pub default_profile: Option<String>,
+    /// Settings that apply to all profiles.
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Named profiles, overriding the top level settings.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Settings>,
+    /// Top level keys that are no setting, e. g. misspelled ones, which are
+    /// refused. Flattened settings cannot deny unknown fields themselves.
+    #[serde(flatten)]
+    unknown_keys: BTreeMap<String, toml::Value>,
+}
+
+impl Config {
+    /// Load the config file.
+    ///
+    /// The config file is the first of
+    /// 1. the `--config` option,
+    /// 2. `$TOTPC_CONFIG`,
+    /// 3. `$XDG_CONFIG_HOME/totpc/config.toml`,
+    /// 4. `$HOME/.config/totpc/config.toml`.
+    ///
+    /// A missing file at one of the default locations yields an empty config.
+    pub fn load(
+        config_file: Option<PathBuf>,
+        env: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self, Error> {
+        let env_path = |name: &str| {
+            env(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        let home = env_path(ENV_HOME);
+        let (path, is_explicit) = if let Some(path) = config_file.or_else(|| env_path(ENV_CONFIG)) {
+            (path, true)
+        } else if let Some(dir) = env_path(ENV_XDG_CONFIG_HOME).filter(|dir| dir.is_absolute()) {
+            (dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME), false)
+        } else if let Some(home) = home.as_deref() {
+            (
+                home.join(".config")
+                    .join(CONFIG_DIR_NAME)
+                    .join(CONFIG_FILE_NAME),
+                false,
+            )
+        } else {
+            return Ok(Self::default());
+        };
+
+        let content = match read_to_string(&path) {
+            Err(err) if err.kind() == ErrorKind::NotFound && !is_explicit => {
+                return Ok(Self::default())
+            }
+            Err(err) => {
+                return Err(Error::io(
+                    format!("error reading config file {}", path.display()),
+                    err,
+                ))
+            }
+            Ok(content) => content,
+        };
+        let mut config = Self::parse(&content).map_err(|message| Error::InvalidConfig {
+            path: path.clone(),
+            message,
+        })?;
+        if let Some(home) = home.as_deref() {
+            config.expand_home(home);
+        }
+        Ok(config)
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(content).map_err(|error| error.message().to_string())?;
+        if let Some(key) = config.unknown_keys.keys().next() {
+            return Err(format!("unknown field `{key}`"));
+        }
+        config.settings.validate()?;
+        for (name, settings) in &config.profiles {
+            settings
+                .validate()
+                .map_err(|message| format!("profile {name}: {message}"))?;
+        }
+        Ok(config)
+    }
+
+    /// Settings of the given profile, or of the default profile if none is
+    /// given, merged with the top level settings.
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings, Error> {
+        match profile.or(self.default_profile.as_deref()) {
+            None => Ok(self.settings.clone()),
+            Some(name) => match self.profiles.get(name) {
+                None => Err(Error::InvalidArgument(format!(
+                    "unknown profile \"{name}\" - must be one of {:?}",
+                    self.profiles.keys().collect::<Vec<_>>()
+                ))),
+                Some(profile) => Ok(self.settings.merge(profile)),
+            },
+        }
+    }
+
+    fn expand_home(&mut self, home: &Path) {
+        for settings in std::iter::once(&mut self.settings).chain(self.profiles.values_mut()) {
+            for path in [&mut settings.store_dir, &mut settings.gnupg_home]
+                .into_iter()
+                .flatten()
+                .chain(settings.stores.values_mut())
+            {
+                if let Ok(relative_path) = path.strip_prefix("~") {
+                    *path = home.join(relative_path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, fs::write, path::PathBuf};
+
+    use tempfile::TempDir;
+
+    use crate::{config::Config, output::Format};
+
+    const CONFIG: &str = r#"
+default_profile = "personal"
+format = "json"
+digits = 8
+
+[stores]
+team = "/team/totpc"
+
+[profiles.personal]
+store_dir = "~/.totpc"
+
+[profiles.work]
+store_dir = "/work/totpc"
+format = "raw"
+
+[profiles.work.stores]
+ops = "~/ops"
+"#;
+
+    #[test]
+    fn profile_settings_override_top_level_settings() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        let settings = config.settings(Some("work")).unwrap();
+        assert_eq!(settings.store_dir, Some(PathBuf::from("/work/totpc")));
+        assert_eq!(settings.format, Some(Format::Raw));
+        assert_eq!(settings.digits, Some(8));
+        assert_eq!(
+            settings.stores.keys().collect::<Vec<_>>(),
+            vec!["ops", "team"]
+        );
+
+        let settings = config.settings(None).unwrap();
+        assert_eq!(settings.store_dir, Some(PathBuf::from("~/.totpc")));
+        assert_eq!(settings.format, Some(Format::Json));
+
+        assert!(config.settings(Some("unknown")).is_err());
+    }
+
+    #[test]
+    fn invalid_settings_fail() {
+        assert!(Config::parse("digits = 5").is_err());
+        assert!(Config::parse("[profiles.work]\nperiod = 0").is_err());
+        assert!(Config::parse("agent_ttl = 0").is_err());
+        assert!(Config::parse("format = \"xml\"").is_err());
+        assert!(Config::parse("[stores]\n\"a:b\" = \"/store\"").is_err());
+    }
+
+    #[test]
+    fn unknown_keys_fail() {
+        assert_eq!(
+            Config::parse("store-dir = \"/store\"").unwrap_err(),
+            "unknown field `store-dir`"
+        );
+        assert!(Config::parse("[profiles.work]\nstore-dir = \"/store\"")
+            .unwrap_err()
+            .starts_with("unknown field `store-dir`"));
+        assert!(Config::parse(CONFIG).is_ok());
+    }
+
+    #[test]
+    fn load_expands_home() {
+        let dir = TempDir::new().unwrap();
+        let config_file = dir.path().join("config.toml");
+        write(&config_file, CONFIG).unwrap();
+
+        let config = Config::load(Some(config_file), |name| {
+            (name == "HOME").then(|| OsString::from("/home/user"))
+        })
+        .unwrap();
+
+        assert_eq!(
+            config.settings(None).unwrap().store_dir,
+            Some(PathBuf::from("/home/user/.totpc"))
+        );
+        assert_eq!(
+            config.settings(Some("work")).unwrap().stores["ops"],
+            PathBuf::from("/home/user/ops")
+        );
+    }
+
+    #[test]
+    fn load_missing_default_config_is_empty() {
+        let config = Config::load(None, |name| {
+            (name == "HOME").then(|| OsString::from("/nonexistent/home"))
+        })
+        .unwrap();
+
+        assert_eq!(config, Config::default());
+        assert!(Config::load(Some(PathBuf::from("/nonexistent/config.toml")), |_| None).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, Serializer};
+
+#[cfg(unix)]
+use crate::agent::{default_socket_path, ENV_AGENT_SOCKET};
+use crate::{config::Settings, error::Error, TOTP_DIR_NAME};
+
+/// Environment variable to set the store directory.
+pub const ENV_STORE_DIR: &str = "TOTPC_STORE_DIR";
+/// Short environment variable to set the store directory.
+pub const ENV_DIR: &str = "TOTPC_DIR";
+/// Environment variable of GnuPG to set its home directory.
+pub const ENV_GNUPG_HOME: &str = "GNUPGHOME";
+/// Separator between store name and identifier, e. g. in `team:aws-root`.
+pub const STORE_SEPARATOR: char = ':';
+const ENV_HOME: &str = "HOME";
+const ENV_XDG_DATA_HOME: &str = "XDG_DATA_HOME";
+#[cfg(unix)]
+const ENV_XDG_RUNTIME_DIR: &str = "XDG_RUNTIME_DIR";
+const XDG_DIR_NAME: &str = "totpc";
+const GNUPG_DIR_NAME: &str = ".gnupg";
+
+/// Origin of a configured directory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    /// Command line option.
+    Option(&'static str),
+    /// Environment variable.
+    Env(&'static str),
+    /// Config file.
+    Config,
+    /// Default location, relative to the given environment variable.
+    Default(&'static str),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Option(option) => write!(f, "option {option}"),
+            Self::Env(name) => write!(f, "environment variable {name}"),
+            Self::Config => f.write_str("config file"),
+            Self::Default(name) => write!(f, "default in ${name}"),
+        }
+    }
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Directories of the key store and of GnuPG, with their origin.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dirs {
+    pub store_dir: PathBuf,
+    pub store_dir_source: Source,
+    pub gnupg_home: PathBuf,
+    pub gnupg_home_source: Source,
+    /// Additional stores by name, each with its own `.gpg-id`.
+    pub stores: BTreeMap<String, PathBuf>,
+    /// Socket of the caching agent, which computes passwords if it is
+    /// running.
+    pub agent_socket: Option<PathBuf>,
+}
+
+impl Dirs {
+    /// Create directories that were set explicitly.
+    pub fn new(store_dir: impl Into<PathBuf>, gnupg_home: impl Into<PathBuf>) -> Self {
+        Self {
+            store_dir: store_dir.into(),
+            store_dir_source: Source::Option("--store-dir"),
+            gnupg_home: gnupg_home.into(),
+            gnupg_home_source: Source::Option("--gnupg-home"),
+            stores: BTreeMap::new(),
+            agent_socket: None,
+        }
+    }
+
+    /// Store directory and identifier within it of the given identifier.
+    /// Identifiers qualified with the name of an additional store, e. g.
+    /// `team:aws-root`, refer to that store, all others to the default store.
+    pub fn locate<'a>(&'a self, identifier: &'a str) -> (&'a Path, &'a str) {
+        identifier
+            .split_once(STORE_SEPARATOR)
+            .and_then(|(name, identifier)| {
+                self.stores.get(name).map(|dir| (dir.as_path(), identifier))
+            })
+            .unwrap_or((self.store_dir.as_path(), identifier))
+    }
+
+    /// Resolve the directories from command line options, environment
+    /// variables and config file settings.
+    ///
+    /// The store directory is the first of
+    /// 1. the `--store-dir` option,
+    /// 2. `$TOTPC_STORE_DIR`,
+    /// 3. `$TOTPC_DIR`,
+    /// 4. `store_dir` of the config file,
+    /// 5. `$HOME/.totpc` if it exists,
+    /// 6. `$XDG_DATA_HOME/totpc`,
+    /// 7. `$HOME/.local/share/totpc`.
+    ///
+    /// The GnuPG home directory is the first of
+    /// 1. the `--gnupg-home` option,
+    /// 2. `$GNUPGHOME`,
+    /// 3. `gnupg_home` of the config file,
+    /// 4. `$HOME/.gnupg`.
+    ///
+    /// The agent socket is `$TOTPC_AGENT_SOCKET`, else `totpc/agent.sock` in
+    /// `$XDG_RUNTIME_DIR` or in a directory of the user in the temporary
+    /// directory.
+    ///
+    /// Empty environment variables are treated as unset. Additional stores
+    /// are taken from the config file.
+    pub fn resolve(
+        store_dir: Option<PathBuf>,
+        gnupg_home: Option<PathBuf>,
+        settings: &Settings,
+        env: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self, Error> {
+        let env_path = |name: &str| {
+            env(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        let home = env_path(ENV_HOME);
+
+        let (store_dir, store_dir_source) = if let Some(dir) = store_dir {
+            (dir, Source::Option("--store-dir"))
+        } else if let Some(dir) = env_path(ENV_STORE_DIR) {
+            (dir, Source::Env(ENV_STORE_DIR))
+        } else if let Some(dir) = env_path(ENV_DIR) {
+            (dir, Source::Env(ENV_DIR))
+        } else if let Some(dir) = settings.store_dir.clone() {
+            (dir, Source::Config)
+        } else if let Some(dir) = home
+            .as_deref()
+            .map(|home| home.join(TOTP_DIR_NAME))
+            .filter(|dir| dir.exists())
+        {
+            (dir, Source::Default(ENV_HOME))
+        } else if let Some(dir) = env_path(ENV_XDG_DATA_HOME).filter(|dir| dir.is_absolute()) {
+            (dir.join(XDG_DIR_NAME), Source::Default(ENV_XDG_DATA_HOME))
+        } else if let Some(home) = home.as_deref() {
+            (
+                xdg_data_home(home).join(XDG_DIR_NAME),
+                Source::Default(ENV_HOME),
+            )
+        } else {
+            return Err(Error::InvalidArgument(format!(
+                "could not determine store directory - set --store-dir or ${ENV_DIR}"
+            )));
+        };
+
+        let (gnupg_home, gnupg_home_source) = if let Some(dir) = gnupg_home {
+            (dir, Source::Option("--gnupg-home"))
+        } else if let Some(dir) = env_path(ENV_GNUPG_HOME) {
+            (dir, Source::Env(ENV_GNUPG_HOME))
+        } else if let Some(dir) = settings.gnupg_home.clone() {
+            (dir, Source::Config)
+        } else if let Some(home) = home.as_deref() {
+            (home.join(GNUPG_DIR_NAME), Source::Default(ENV_HOME))
+        } else {
+            return Err(Error::InvalidArgument(format!(
+                "could not determine GnuPG home directory - set --gnupg-home or ${ENV_GNUPG_HOME}"
+            )));
+        };
+
+        #[cfg(unix)]
+        let agent_socket = Some(
+            env_path(ENV_AGENT_SOCKET)
+                .unwrap_or_else(|| default_socket_path(env_path(ENV_XDG_RUNTIME_DIR))),
+        );
+        #[cfg(not(unix))]
+        let agent_socket = None;
+
+        Ok(Self {
+            store_dir,
+            store_dir_source,
+            gnupg_home,
+            gnupg_home_source,
+            stores: settings.stores.clone(),
+            agent_socket,
+        })
+    }
+}
+
+fn xdg_data_home(home: &Path) -> PathBuf {
+    home.join(".local").join("share")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        ffi::OsString,
+        fs::create_dir,
+        path::{Path, PathBuf},
+    };
+
+    use tempfile::TempDir;
+
+    use crate::{
+        config::Settings,
+        dirs::{Dirs, Source},
+        TOTP_DIR_NAME,
+    };
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), OsString::from(value)))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn options_take_precedence() {
+        let dirs = Dirs::resolve(
+            Some(PathBuf::from("/store")),
+            Some(PathBuf::from("/gnupg")),
+            &Settings::default(),
+            env(&[("TOTPC_DIR", "/env/store"), ("GNUPGHOME", "/env/gnupg")]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dirs,
+            Dirs {
+                agent_socket: dirs.agent_socket.clone(),
+                ..Dirs::new("/store", "/gnupg")
+            }
+        );
+    }
+
+    #[test]
+    fn environment_variables_take_precedence_over_defaults() {
+        let dirs = Dirs::resolve(
+            None,
+            None,
+            &Settings::default(),
+            env(&[
+                ("HOME", "/home/user"),
+                ("TOTPC_STORE_DIR", "/env/store"),
+                ("TOTPC_DIR", "/env/dir"),
+                ("GNUPGHOME", "/env/gnupg"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(dirs.store_dir, PathBuf::from("/env/store"));
+        assert_eq!(dirs.store_dir_source, Source::Env("TOTPC_STORE_DIR"));
+        assert_eq!(dirs.gnupg_home, PathBuf::from("/env/gnupg"));
+        assert_eq!(dirs.gnupg_home_source, Source::Env("GNUPGHOME"));
+    }
+
+    #[test]
+    fn environment_variables_take_precedence_over_config() {
+        let settings = Settings {
+            store_dir: Some(PathBuf::from("/config/store")),
+            gnupg_home: Some(PathBuf::from("/config/gnupg")),
+            ..Default::default()
+        };
+
+        let dirs = Dirs::resolve(
+            None,
+            None,
+            &settings,
+            env(&[("HOME", "/home/user"), ("GNUPGHOME", "/env/gnupg")]),
+        )
+        .unwrap();
+
+        assert_eq!(dirs.store_dir, PathBuf::from("/config/store"));
+        assert_eq!(dirs.store_dir_source, Source::Config);
+        assert_eq!(dirs.gnupg_home, PathBuf::from("/env/gnupg"));
+    }
+
+    #[test]
+    fn existing_home_store_takes_precedence_over_xdg() {
+        let home = TempDir::new().unwrap();
+        let home_path = home.path().to_str().unwrap();
+        let vars = [("HOME", home_path), ("XDG_DATA_HOME", "/xdg")];
+
+        let dirs = Dirs::resolve(None, None, &Settings::default(), env(&vars)).unwrap();
+        assert_eq!(dirs.store_dir, PathBuf::from("/xdg/totpc"));
+        assert_eq!(dirs.gnupg_home, home.path().join(".gnupg"));
+
+        create_dir(home.path().join(TOTP_DIR_NAME)).unwrap();
+        let dirs = Dirs::resolve(None, None, &Settings::default(), env(&vars)).unwrap();
+        assert_eq!(dirs.store_dir, home.path().join(TOTP_DIR_NAME));
+    }
+
+    #[test]
+    fn xdg_defaults_to_home() {
+        let dirs = Dirs::resolve(
+            None,
+            None,
+            &Settings::default(),
+            env(&[("HOME", "/nonexistent/home")]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dirs.store_dir,
+            PathBuf::from("/nonexistent/home/.local/share/totpc")
+        );
+    }
+
+    #[test]
+    fn locate_qualified_identifiers() {
+        let mut dirs = Dirs::new("/store", "/gnupg");
+        dirs.stores
+            .insert("team".to_string(), PathBuf::from("/team"));
+
+        assert_eq!(
+            dirs.locate("team:aws-root"),
+            (Path::new("/team"), "aws-root")
+        );
+        assert_eq!(dirs.locate("aws-root"), (Path::new("/store"), "aws-root"));
+        assert_eq!(dirs.locate("other:id"), (Path::new("/store"), "other:id"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn agent_socket_is_in_runtime_dir_unless_set() {
+        let vars = [
+            ("HOME", "/home/user"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+        ];
+        let dirs = Dirs::resolve(None, None, &Settings::default(), env(&vars)).unwrap();
+        assert_eq!(
+            dirs.agent_socket.as_deref(),
+            Some(Path::new("/run/user/1000/totpc/agent.sock"))
+        );
+
+        let vars = [
+            ("HOME", "/home/user"),
+            ("TOTPC_AGENT_SOCKET", "/agent.sock"),
+        ];
+        let dirs = Dirs::resolve(None, None, &Settings::default(), env(&vars)).unwrap();
+        assert_eq!(dirs.agent_socket.as_deref(), Some(Path::new("/agent.sock")));
+    }
+
+    #[test]
+    fn resolve_fails_without_home() {
+        assert!(Dirs::resolve(None, None, &Settings::default(), env(&[])).is_err());
+    }
+}
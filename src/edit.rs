@@ -0,0 +1,169 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::{read_dir, read_to_string, File, OpenOptions},
+    io::{ErrorKind, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tempfile::Builder;
+
+use crate::error::Error;
+
+const ENV_VISUAL: &str = "VISUAL";
+const ENV_EDITOR: &str = "EDITOR";
+const ENV_XDG_RUNTIME_DIR: &str = "XDG_RUNTIME_DIR";
+const DEFAULT_EDITOR: &str = "vi";
+const SHM_DIR: &str = "/dev/shm";
+const TEMP_FILE_PREFIX: &str = "totpc-";
+const TEMP_FILE_SUFFIX: &str = ".toml";
+
+/// Editor command of `$VISUAL` or `$EDITOR`, `vi` by default.
+pub fn editor() -> OsString {
+    [ENV_VISUAL, ENV_EDITOR]
+        .into_iter()
+        .filter_map(env::var_os)
+        .find(|editor| !editor.is_empty())
+        .unwrap_or_else(|| OsString::from(DEFAULT_EDITOR))
+}
+
+/// Let the user edit the given content with the given editor command and
+/// return the edited content.
+///
+/// The content is written to a temporary file that only the user can read,
+/// in a directory that only the user can access, on tmpfs if available, so
+/// that it never reaches the disk. All files in the directory, including swap
+/// and backup files of the editor, are overwritten and deleted afterwards,
+/// also when editing fails.
+pub fn edit_in_editor(editor: &OsStr, content: &str) -> Result<String, Error> {
+    let dir = Builder::new()
+        .prefix(TEMP_FILE_PREFIX)
+        .tempdir_in(temp_dir())
+        .map_err(|err| Error::io("error creating temporary directory", err))?;
+    let mut file = Builder::new()
+        .prefix(TEMP_FILE_PREFIX)
+        .suffix(TEMP_FILE_SUFFIX)
+        .tempfile_in(dir.path())
+        .map_err(|err| Error::io("error creating temporary file", err))?;
+    file.write_all(content.as_bytes())
+        .and_then(|()| file.flush())
+        .map_err(|err| Error::io("error writing temporary file", err))?;
+    let result = run_editor(editor, file.path()).and_then(|()| {
+        read_to_string(file.path()).map_err(|err| Error::io("error reading temporary file", err))
+    });
+    // editors that save by renaming a new file leave the original one only
+    // reachable through the open handle
+    let shredded = shred(file.as_file_mut());
+    shred_dir(dir.path()).and(shredded)?;
+    result
+}
+
+/// Directory for temporary files, preferably on tmpfs.
+fn temp_dir() -> PathBuf {
+    let shm_dir = Path::new(SHM_DIR);
+    if shm_dir.is_dir() {
+        return shm_dir.to_path_buf();
+    }
+    env::var_os(ENV_XDG_RUNTIME_DIR)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(env::temp_dir)
+}
+
+/// Run the editor through the shell, so that it may contain arguments, e. g.
+/// `code --wait`.
+fn run_editor(editor: &OsStr, path: &Path) -> Result<(), Error> {
+    let mut script = editor.to_os_string();
+    script.push(r#" "$@""#);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .arg(editor)
+        .arg(path)
+        .status()
+        .map_err(|err| Error::io("error running editor", err))?;
+    if !status.success() {
+        return Err(Error::Other(format!(
+            "editor {} exited with {status} - entry is unchanged",
+            editor.to_string_lossy()
+        )));
+    }
+    Ok(())
+}
+
+/// Overwrite all files in the directory and its subdirectories with zeros
+/// before they are deleted.
+fn shred_dir(dir: &Path) -> Result<(), Error> {
+    let entries = read_dir(dir).map_err(|err| Error::io("error shredding temporary files", err))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io("error shredding temporary files", err))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|err| Error::io("error shredding temporary files", err))?;
+        if file_type.is_dir() {
+            shred_dir(&entry.path())?;
+        } else if file_type.is_file() {
+            let mut file = match OpenOptions::new().write(true).open(entry.path()) {
+                // deleted by the editor in the meantime
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                result => result.map_err(|err| Error::io("error shredding temporary file", err))?,
+            };
+            shred(&mut file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Overwrite the file with zeros before it is deleted.
+fn shred(file: &mut File) -> Result<(), Error> {
+    let length = file
+        .metadata()
+        .map_err(|err| Error::io("error shredding temporary file", err))?
+        .len();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.write_all(&vec![0; length as usize]))
+        .and_then(|()| file.sync_all())
+        .map_err(|err| Error::io("error shredding temporary file", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs::read_to_string, path::Path};
+
+    use tempfile::TempDir;
+
+    use crate::edit::edit_in_editor;
+
+    #[test]
+    fn content_is_edited() {
+        let editor = r#"sh -c 'printf "NEW KEY\n" > "$1"' sh"#;
+
+        let content = edit_in_editor(OsStr::new(editor), "OLD KEY\n").unwrap();
+
+        assert_eq!(content, "NEW KEY\n");
+    }
+
+    #[test]
+    fn files_of_editor_are_deleted() {
+        let dir = TempDir::new().unwrap();
+        let recorded = dir.path().join("recorded");
+        // keep a backup and a swap file and save by renaming, like some
+        // editors do, and record the directory of the file
+        let editor = format!(
+            r#"sh -c 'cp "$1" "$1~" && cp "$1" "$1.swp" && printf NEW > "$1.new" && mv "$1.new" "$1" && dirname "$1" > "{}"' sh"#,
+            recorded.display()
+        );
+
+        let content = edit_in_editor(OsStr::new(&editor), "OLD").unwrap();
+
+        assert_eq!(content, "NEW");
+        let temp_dir = read_to_string(recorded).unwrap();
+        assert!(!Path::new(temp_dir.trim()).exists());
+    }
+
+    #[test]
+    fn failing_editor_fails() {
+        assert!(edit_in_editor(OsStr::new("false"), "KEY").is_err());
+    }
+}
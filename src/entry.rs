@@ -0,0 +1,269 @@
+//! Plaintext payload of an entry, which is encrypted into its key file.
+//!
+//! The payload is a TOML document with a format version:
+//!
+//! ```toml
+//! version = 1
+//! secret = "JBSWY3DPEHPK3PXP"
+//! algorithm = "SHA1"
+//! digits = 6
+//! period = 30
+//! issuer = "Example"
+//! account = "alice@example.com"
+//! url = "https://example.com/login"
+//! tags = ["personal", "prod"]
+//! notes = "recovery codes are in the safe"
+//! created = "2026-10-18T12:00:00Z"
+//! ```
+//!
+//! Key files written by earlier versions contain only the Base32 encoded
+//! secret. They are read as entries with default parameters.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    base32::decode,
+    compute::{Algorithm, DEFAULT_DIGITS, DIGITS, TIME_STEP_INTERVAL},
+    error::Error,
+    totp::Totp,
+};
+
+/// Current version of the payload format.
+pub const ENTRY_VERSION: u32 = 1;
+
+/// Descriptive fields of an entry, which do not affect the computed TOTPs.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Metadata {
+    /// Provider of the account, e. g. "Example".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Name of the account at the issuer, e. g. "alice@example.com".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Web address of the login page, e. g. "https://example.com/login".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Labels to group entries by, e. g. "prod", sorted and without
+    /// duplicates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Counter of HOTP entries, kept for export.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counter: Option<u64>,
+    /// Free text notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Creation date as RFC 3339 timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+}
+
+/// Secret, parameters and metadata of an entry.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    /// Version of the payload format.
+    pub version: u32,
+    /// Base32 encoded secret key.
+    pub secret: String,
+    /// Hash algorithm of the HMAC.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// Number of digits of the TOTP.
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    /// Length of a time step in seconds.
+    #[serde(default = "default_period")]
+    pub period: u64,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+fn default_digits() -> u32 {
+    DEFAULT_DIGITS
+}
+
+fn default_period() -> u64 {
+    TIME_STEP_INTERVAL
+}
+
+impl Entry {
+    /// Entry with the given Base32 encoded secret and default parameters.
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            version: ENTRY_VERSION,
+            secret: secret.into(),
+            algorithm: Algorithm::default(),
+            digits: DEFAULT_DIGITS,
+            period: TIME_STEP_INTERVAL,
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Parse a decrypted payload. A payload of a single line that is no TOML
+    /// document is read as bare Base32 encoded secret.
+    pub fn parse(payload: &str) -> Result<Self, Error> {
+        let mut entry = match toml::from_str::<Self>(payload) {
+            Ok(entry) => entry,
+            Err(_) if !payload.trim().contains('\n') && payload.parse::<toml::Table>().is_err() => {
+                Self::new(payload)
+            }
+            Err(error) => {
+                return Err(Error::InvalidKey(format!(
+                    "invalid entry - {}",
+                    error.message()
+                )))
+            }
+        };
+        entry.secret = parse_secret(&entry.secret)?;
+        entry.validate()?;
+        Ok(entry)
+    }
+
+    /// Payload of the entry in the current format.
+    pub fn to_payload(&self) -> Result<String, Error> {
+        toml::to_string(&Self {
+            version: ENTRY_VERSION,
+            ..self.clone()
+        })
+        .map_err(|error| Error::Other(format!("error serializing entry - {error}")))
+    }
+
+    /// Decoded secret key.
+    pub fn key(&self) -> Result<Vec<u8>, Error> {
+        decode(&self.secret)
+    }
+
+    /// TOTP generator of the secret and parameters of the entry.
+    pub fn totp(&self) -> Result<Totp, Error> {
+        Totp::try_from(self)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if !(1..=ENTRY_VERSION).contains(&self.version) {
+            return Err(Error::InvalidKey(format!(
+                "unsupported entry version {} - update totpc",
+                self.version
+            )));
+        }
+        if !DIGITS.contains(&self.digits) {
+            return Err(Error::InvalidKey(format!(
+                "unsupported number of digits {} - must be one of {DIGITS:?}",
+                self.digits
+            )));
+        }
+        if self.period == 0 {
+            return Err(Error::InvalidKey(
+                "period must be greater than 0".to_string(),
+            ));
+        }
+        for tag in &self.metadata.tags {
+            parse_tag(tag).map_err(|_| Error::InvalidKey(format!("invalid tag {tag:?}")))?;
+        }
+        Ok(())
+    }
+}
+
+/// Normalize an entered Base32 encoded secret, ignoring spaces and case, and
+/// check that it is valid.
+pub fn parse_secret(input: &str) -> Result<String, Error> {
+    let secret = input.trim().replace(' ', "").to_uppercase();
+    if secret.is_empty() {
+        return Err(Error::EmptyKey);
+    }
+    decode(&secret)?;
+    Ok(secret)
+}
+
+/// Check that an entered tag is a single word without commas.
+pub fn parse_tag(input: &str) -> Result<String, Error> {
+    let tag = input.trim();
+    if tag.is_empty() || tag.contains(|char: char| char.is_whitespace() || char == ',') {
+        return Err(Error::InvalidArgument(format!(
+            "invalid tag {input:?} - must be a single word without commas"
+        )));
+    }
+    Ok(tag.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compute::Algorithm,
+        entry::{parse_secret, parse_tag, Entry, ENTRY_VERSION},
+        Error,
+    };
+
+    #[test]
+    fn bare_secret_is_read_with_defaults() {
+        let entry = Entry::parse("JBSWY3DPEHPK3PXP\n").unwrap();
+
+        assert_eq!(entry, Entry::new("JBSWY3DPEHPK3PXP"));
+        assert_eq!(entry.algorithm, Algorithm::Sha1);
+        assert_eq!((entry.digits, entry.period), (6, 30));
+    }
+
+    #[test]
+    fn payload_is_written_and_read() {
+        let mut entry = Entry::new("JBSWY3DPEHPK3PXP");
+        entry.algorithm = Algorithm::Sha256;
+        entry.digits = 8;
+        entry.metadata.issuer = Some("Example".to_string());
+        entry.metadata.tags = vec!["aws".to_string(), "prod".to_string()];
+        entry.metadata.notes = Some("first line\nsecond line".to_string());
+
+        let payload = entry.to_payload().unwrap();
+
+        assert!(payload.starts_with(&format!("version = {ENTRY_VERSION}\n")));
+        assert_eq!(Entry::parse(&payload).unwrap(), entry);
+    }
+
+    #[test]
+    fn payload_with_missing_parameters_uses_defaults() {
+        let entry = Entry::parse("version = 1\nsecret = \"jbsw y3dp ehpk 3pxp\"\n").unwrap();
+
+        assert_eq!(entry, Entry::new("JBSWY3DPEHPK3PXP"));
+    }
+
+    #[test]
+    fn single_line_toml_payload_is_not_read_as_bare_secret() {
+        let error = Entry::parse("secret = \"JBSWY3DPEHPK3PXP\"\n").unwrap_err();
+
+        assert!(
+            matches!(&error, Error::InvalidKey(message)
+                if message.starts_with("invalid entry - missing field `version`")),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_payload_fails() {
+        for payload in [
+            "version = 2\nsecret = \"JBSWY3DPEHPK3PXP\"",
+            "version = 1\nsecret = \"JBSWY3DPEHPK3PXP\"\ndigits = 5",
+            "version = 1\nsecret = \"JBSWY3DPEHPK3PXP\"\nalgorithm = \"MD5\"",
+            "version = 1\nsecret = \"base 10\"",
+            "secret = \"JBSWY3DPEHPK3PXP\"\ndigits = 6",
+            "version = 1\nsecret = \"JBSWY3DPEHPK3PXP\"\ntags = [\"a b\"]",
+        ] {
+            assert!(
+                matches!(Entry::parse(payload), Err(Error::InvalidKey(_))),
+                "{payload}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_secret_normalizes_input() {
+        assert_eq!(parse_secret(" jbsw y3dp\n").unwrap(), "JBSWY3DP");
+        assert!(matches!(parse_secret("  "), Err(Error::EmptyKey)));
+        assert!(matches!(parse_secret("1"), Err(Error::InvalidKey(_))));
+    }
+
+    #[test]
+    fn parse_tag_rejects_separators() {
+        assert_eq!(parse_tag(" prod ").unwrap(), "prod");
+        for tag in ["", "a b", "a,b"] {
+            assert!(matches!(parse_tag(tag), Err(Error::InvalidArgument(_))));
+        }
+    }
+}
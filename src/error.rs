@@ -0,0 +1,182 @@
+use std::{fmt::Display, io, path::PathBuf};
+
+use crate::file::Warning;
+
+/// Error of a totpc command.
+///
+/// Every error maps to an exit code, so that wrappers can tell failures apart:
+///
+/// | Code | Error                                                   |
+/// |------|---------------------------------------------------------|
+/// | 1    | I/O and other errors                                    |
+/// | 2    | Invalid usage or config, e. g. an unknown flag          |
+/// | 3    | Store not initialized                                   |
+/// | 4    | Entry not found                                         |
+/// | 5    | Entry or store already exists                           |
+/// | 6    | Decryption failed                                       |
+/// | 7    | Decryption cancelled, e. g. in pinentry                 |
+/// | 8    | Encryption failed                                       |
+/// | 9    | Invalid key, e. g. not Base32 encoded                   |
+/// | 10   | Invalid identifier                                      |
+/// | 11   | GPG is not installed                                    |
+/// | 12   | Code did not verify                                     |
+/// | 13   | Store check found problems                              |
+/// | 14   | Computing some of several entries failed                |
+///
+/// Errors reported by the agent keep the exit code of the original error.
+#[derive(Debug)]
+pub enum Error {
+    /// Argument value is invalid.
+    InvalidArgument(String),
+    /// Config file is invalid.
+    InvalidConfig { path: PathBuf, message: String },
+    /// No GPG id has been set for the given directory.
+    NotInitialized(PathBuf),
+    /// A GPG id has already been set for the given directory.
+    AlreadyInitialized { gpg_id: String, path: PathBuf },
+    /// No entry stored for the identifier.
+    EntryNotFound(String),
+    /// An entry is already stored for the identifier.
+    EntryExists(String),
+    /// GPG failed to decrypt, with its error output.
+    DecryptionFailed(String),
+    /// Decryption was cancelled by the user.
+    DecryptionCancelled,
+    /// GPG failed to encrypt, with its error output.
+    EncryptionFailed(String),
+    /// Entered key is empty.
+    EmptyKey,
+    /// Key is invalid, e. g. not Base32 encoded.
+    InvalidKey(String),
+    /// Identifier cannot be used as entry name.
+    InvalidIdentifier(String),
+    /// GPG command could not be found.
+    GpgMissing,
+    /// Code does not match any time step in the window.
+    InvalidCode {
+        identifier: String,
+        code: String,
+        window: u64,
+    },
+    /// Check of the stores found problems, with the number of entries
+    /// checked.
+    StoreProblems {
+        checked: usize,
+        problems: Vec<Warning>,
+    },
+    /// Computing the passwords of several entries failed for some of them,
+    /// with the rendered output of the others.
+    ComputeFailed {
+        output: String,
+        failed: usize,
+        total: usize,
+    },
+    /// Request to the agent failed, with the message and exit code of the
+    /// error in the agent.
+    Agent { message: String, exit_code: i32 },
+    /// Reading or writing failed.
+    Io { context: String, source: io::Error },
+    /// Any other error.
+    Other(String),
+}
+
+impl Error {
+    /// Exit code of the process when failing with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Io { .. } | Self::Other(_) => 1,
+            Self::InvalidArgument(_) | Self::InvalidConfig { .. } => 2,
+            Self::NotInitialized(_) => 3,
+            Self::EntryNotFound(_) => 4,
+            Self::AlreadyInitialized { .. } | Self::EntryExists(_) => 5,
+            Self::DecryptionFailed(_) => 6,
+            Self::DecryptionCancelled => 7,
+            Self::EncryptionFailed(_) => 8,
+            Self::EmptyKey | Self::InvalidKey(_) => 9,
+            Self::InvalidIdentifier(_) => 10,
+            Self::GpgMissing => 11,
+            Self::InvalidCode { .. } => 12,
+            Self::StoreProblems { .. } => 13,
+            Self::ComputeFailed { .. } => 14,
+            Self::Agent { exit_code, .. } => *exit_code,
+        }
+    }
+
+    /// Create an I/O error with a description of the failed operation.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidArgument(message) => f.write_str(message),
+            Self::InvalidConfig { path, message } => {
+                write!(f, "invalid config file {} - {message}", path.display())
+            }
+            Self::NotInitialized(path) => write!(
+                f,
+                "totp computer is not initialized in {} - run init with a gpg id first",
+                path.display()
+            ),
+            Self::AlreadyInitialized { gpg_id, path } => write!(
+                f,
+                "existing gpg id found: {gpg_id}\nDelete existing id first to re-initialize: rm {}",
+                path.display()
+            ),
+            Self::EntryNotFound(identifier) => write!(f, "no entry found for {identifier}"),
+            Self::EntryExists(identifier) => write!(f, "entry for {identifier} exists"),
+            Self::DecryptionFailed(message) => write!(f, "decryption failed - {message}"),
+            Self::DecryptionCancelled => f.write_str("decryption cancelled"),
+            Self::EncryptionFailed(message) => write!(f, "encryption failed - {message}"),
+            Self::EmptyKey => f.write_str("key must not be empty"),
+            Self::InvalidKey(message) => write!(f, "invalid key - {message}"),
+            Self::InvalidIdentifier(identifier) => write!(
+                f,
+                "invalid identifier \"{identifier}\" - it and its folders separated by / must not be empty, start with a dot or contain backslashes or colons, which only follow the name of a configured store"
+            ),
+            Self::GpgMissing => f.write_str("gpg command not found - install GnuPG"),
+            Self::InvalidCode {
+                identifier,
+                code,
+                window,
+            } => write!(
+                f,
+                "code {code} is invalid for {identifier} (checked {window} time steps before and after the current one)"
+            ),
+            Self::StoreProblems { checked, problems } => {
+                let noun = match problems.len() {
+                    1 => "problem",
+                    _ => "problems",
+                };
+                write!(
+                    f,
+                    "found {} {noun} checking {checked} entries:",
+                    problems.len()
+                )?;
+                problems
+                    .iter()
+                    .try_for_each(|problem| write!(f, "\n{problem}"))
+            }
+            Self::ComputeFailed { failed, total, .. } => {
+                write!(f, "failed to compute {failed} of {total} entries")
+            }
+            Self::Agent { message, .. } => write!(f, "agent: {message}"),
+            Self::Io { context, source } => write!(f, "{context} - {source}"),
+            Self::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
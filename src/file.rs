@@ -1,175 +1,638 @@
 use std::{
     ffi::OsStr,
-    fs::{create_dir, read_to_string, remove_file, write},
-    io::Write,
-    path::Path,
-    process::{Command, Stdio},
+    fmt::Display,
+    fs::{copy, create_dir_all, read_to_string, remove_dir, remove_file, rename, write, ReadDir},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
 };
 
+use serde::{Serialize, Serializer};
+
+use crate::{dirs::STORE_SEPARATOR, error::Error};
+
 const GPG_COMMAND: &str = "gpg";
-const GPG_ID_FILE_NAME: &str = ".gpg-id";
-const GPG_FILE_EXTENSION: &str = "gpg";
+pub const GPG_ID_FILE_NAME: &str = ".gpg-id";
+pub const GPG_FILE_EXTENSION: &str = "gpg";
+pub const INDEX_FILE_NAME: &str = ".index.gpg";
+/// Prefix of the status lines that GPG writes with `--status-fd`.
+const GPG_STATUS_PREFIX: &str = "[GNUPG:] ";
+/// GPG error codes of operations cancelled by the user, e. g. in pinentry.
+const GPG_CANCELLED_ERROR_CODES: [u32; 2] = [99, 198];
 
 /// Initialize a directory for usage with totpc. Creates a file with the GPG id
 /// in it.
-pub fn init(totp_dir: &Path, gpg_id: &str) -> Result<(), String> {
+pub fn init(totp_dir: &Path, gpg_id: &str) -> Result<(), Error> {
     let gpg_id_file = totp_dir.join(GPG_ID_FILE_NAME);
     if gpg_id_file.is_file() {
         let existing_gpg_id = read_to_string(gpg_id_file.clone())
-            .map_err(|err| format!("Error reading gpg id file - {err}"))?;
+            .map_err(|err| Error::io("error reading gpg id file", err))?;
         if !existing_gpg_id.is_empty() {
-            return Err(format!(
-                "Error initializing - existing gpg id found: {existing_gpg_id}\nDelete existing id first to re-initialize: rm {}", gpg_id_file.display()
-            ));
+            return Err(Error::AlreadyInitialized {
+                gpg_id: existing_gpg_id,
+                path: gpg_id_file,
+            });
         }
     } else if !totp_dir.exists() {
-        println!("totp dir {:?}", totp_dir);
-        create_dir(totp_dir).map_err(|err| format!("Error creating totp dir - {err}"))?;
+        create_dir_all(totp_dir).map_err(|err| Error::io("error creating totp dir", err))?;
     }
-    write(gpg_id_file, gpg_id).map_err(|err| format!("Error writing gpg id file - {err}"))?;
+    write(gpg_id_file, gpg_id).map_err(|err| Error::io("error writing gpg id file", err))?;
     Ok(())
 }
 
 /// Read GPG id from the given totpc directory.
-fn read_gpg_id(totp_dir: &Path) -> Result<String, String> {
+pub fn read_gpg_id(totp_dir: &Path) -> Result<String, Error> {
     read_to_string(totp_dir.join(GPG_ID_FILE_NAME))
         .map(|gpg_id| gpg_id.trim().to_string())
-        .map_err(|err| format!("Error reading gpg id - {err}"))
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => Error::NotInitialized(totp_dir.to_path_buf()),
+            _ => Error::io("error reading gpg id", err),
+        })
+}
+
+/// GPG id that the file or folder at the given path in the store directory is
+/// encrypted for: the one of the closest folder containing it that has a
+/// `.gpg-id` file, else the one of the store directory.
+pub fn resolve_gpg_id(totp_dir: &Path, path: &Path) -> Result<String, Error> {
+    match folders_of(totp_dir, path).find(|dir| dir.join(GPG_ID_FILE_NAME).is_file()) {
+        Some(dir) => read_gpg_id(dir),
+        None => read_gpg_id(totp_dir),
+    }
+}
+
+/// Folders containing the given path in the store directory, innermost first,
+/// without the store directory itself.
+fn folders_of<'a>(totp_dir: &'a Path, path: &'a Path) -> impl Iterator<Item = &'a Path> {
+    path.ancestors()
+        .skip(1)
+        .take_while(move |dir| dir.starts_with(totp_dir) && *dir != totp_dir)
+}
+
+/// Path of the key file of the given identifier. Identifiers of entries in
+/// folders separate the folders with `/`, e. g. `work/github`. Fails for
+/// identifiers that would point outside of the given directory or to hidden
+/// files or folders.
+pub fn key_file_path(totp_dir: &Path, identifier: &str) -> Result<PathBuf, Error> {
+    // an empty identifier or last folder name results in the hidden ".gpg"
+    store_path(totp_dir, &format!("{identifier}.{GPG_FILE_EXTENSION}"))
+        .ok_or_else(|| Error::InvalidIdentifier(identifier.to_string()))
+}
+
+/// Path of the given folder, e. g. `work` or `work/aws`, in the given
+/// directory. Fails like [`key_file_path`].
+pub fn folder_path(totp_dir: &Path, folder: &str) -> Result<PathBuf, Error> {
+    store_path(totp_dir, folder.trim_end_matches('/'))
+        .ok_or_else(|| Error::InvalidIdentifier(folder.to_string()))
+}
+
+/// Path of the given name with `/` separated parts in the store directory, or
+/// `None` if any part is empty, hidden or contains other path separators or
+/// the store separator, e. g. of an unknown store in `typo:github`.
+fn store_path(totp_dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = totp_dir.to_path_buf();
+    for component in name.split('/') {
+        if component.is_empty()
+            || component.starts_with('.')
+            || component.contains(['\\', '\0', STORE_SEPARATOR])
+        {
+            return None;
+        }
+        path.push(component);
+    }
+    Some(path)
+}
+
+/// Path of the encrypted index file in the given directory.
+pub fn index_file_path(totp_dir: &Path) -> PathBuf {
+    totp_dir.join(INDEX_FILE_NAME)
+}
+
+/// Spawn the GPG command with the given arguments.
+fn spawn_gpg(command: &mut Command) -> Result<Child, Error> {
+    command.spawn().map_err(|err| match err.kind() {
+        ErrorKind::NotFound => Error::GpgMissing,
+        _ => Error::io(format!("error running command {GPG_COMMAND}"), err),
+    })
+}
+
+/// Problem with a single file that was skipped while listing a directory.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Warning {
+    /// Path of the skipped file, if known.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_path_lossy"
+    )]
+    pub path: Option<PathBuf>,
+    /// Reason the file was skipped.
+    pub message: String,
+}
+
+/// Paths of warnings are typically not valid UTF-8, so serialize them lossily
+/// instead of failing.
+fn serialize_path_lossy<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        None => serializer.serialize_none(),
+        Some(path) => serializer.serialize_str(&path.to_string_lossy()),
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            None => f.write_str(&self.message),
+            Some(path) => write!(f, "{} - {}", path.display(), self.message),
+        }
+    }
+}
+
+/// Identifiers in a directory, with warnings about files that were skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Listing {
+    /// Identifiers in ascending order.
+    pub identifiers: Vec<String>,
+    /// Files that were skipped.
+    pub warnings: Vec<Warning>,
 }
 
-/// List all stored identifiers in the given directory.
-pub fn list_identifiers(totp_dir: &Path) -> Result<Vec<String>, String> {
-    let files_in_dir = totp_dir
-        .read_dir()
-        .map_err(|err| format!("Error reading dir {} - {err}", totp_dir.display()))?;
-    let mut identifiers: Vec<String> = files_in_dir
-        .filter_map(|entry_result| match entry_result {
+/// List all stored identifiers in the given directory and its folders.
+/// Entries that cannot be read are reported as warnings instead of failing
+/// the whole listing.
+pub fn list_identifiers(totp_dir: &Path) -> Result<Listing, Error> {
+    let files_in_dir = totp_dir.read_dir().map_err(|err| match err.kind() {
+        ErrorKind::NotFound => Error::NotInitialized(totp_dir.to_path_buf()),
+        _ => Error::io(format!("error reading dir {}", totp_dir.display()), err),
+    })?;
+    let mut listing = Listing::default();
+    list_folder(totp_dir, files_in_dir, "", &mut listing);
+    listing.identifiers.sort();
+    Ok(listing)
+}
+
+/// Add the identifiers of the files in the given folder and its subfolders to
+/// the listing, prefixed with the path of the folder in the store.
+fn list_folder(dir: &Path, files_in_dir: ReadDir, prefix: &str, listing: &mut Listing) {
+    for entry_result in files_in_dir {
+        let entry = match entry_result {
             Err(err) => {
-                eprintln!("Error reading dir - {err}");
-                None
+                listing.warnings.push(Warning {
+                    path: None,
+                    message: format!("error reading dir {} - {err}", dir.display()),
+                });
+                continue;
             }
-            Ok(entry) => {
-                if entry
-                    .path()
-                    .extension()
-                    .and_then(OsStr::to_str)
-                    .map(|ext| ext == GPG_FILE_EXTENSION)
-                    .unwrap_or_else(|| false)
-                {
-                    entry
-                        .file_name()
-                        .to_str()
-                        .map(|file_name| file_name.replace(&format!(".{GPG_FILE_EXTENSION}"), ""))
-                } else {
-                    None
-                }
+            Ok(entry) => entry,
+        };
+        let path = entry.path();
+        // symbolic links to folders are not followed
+        let is_folder = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        // hidden files like the index and hidden folders like .git are not
+        // entries
+        if (!is_folder && path.extension().and_then(OsStr::to_str) != Some(GPG_FILE_EXTENSION))
+            || entry.file_name().to_string_lossy().starts_with('.')
+        {
+            continue;
+        }
+        let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+            listing.warnings.push(Warning {
+                path: Some(path),
+                message: "file name is not valid UTF-8".to_string(),
+            });
+            continue;
+        };
+        if file_name.contains(STORE_SEPARATOR) {
+            listing.warnings.push(Warning {
+                path: Some(path),
+                message: format!(
+                    "name contains {STORE_SEPARATOR}, which separates store names - rename it"
+                ),
+            });
+            continue;
+        }
+        if !is_folder {
+            let identifier = file_name
+                .strip_suffix(&format!(".{GPG_FILE_EXTENSION}"))
+                .unwrap_or(&file_name);
+            listing.identifiers.push(format!("{prefix}{identifier}"));
+            continue;
+        }
+        match path.read_dir() {
+            Err(err) => listing.warnings.push(Warning {
+                message: format!("error reading dir - {err}"),
+                path: Some(path),
+            }),
+            Ok(files_in_folder) => {
+                let prefix = format!("{prefix}{file_name}/");
+                list_folder(&path, files_in_folder, &prefix, listing);
             }
-        })
-        .collect();
-    identifiers.sort();
-    Ok(identifiers)
+        }
+    }
 }
 
 /// Encrypt and store key to file under name <identifier> in given directory.
+/// GPG prompts before overwriting an existing file.
 pub fn write_encrypted_key_to_file(
     gpg_home_dir: &Path,
     totp_dir: &Path,
     identifier: &str,
     key: &str,
-) -> Result<(), String> {
-    let gpg_id = read_gpg_id(totp_dir)?;
-    let new_file_name = format!("{identifier}.{GPG_FILE_EXTENSION}");
-    let new_file_path = totp_dir.join(new_file_name);
-    let mut gpg_cmd = Command::new(GPG_COMMAND)
-        .arg("--homedir")
-        .arg(gpg_home_dir)
-        .arg("--encrypt")
-        .arg("--recipient")
-        .arg(gpg_id)
-        .arg("-o")
-        .arg(&new_file_path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .map_err(|err| format!("Error running encryption command {GPG_COMMAND} - {err}"))?;
+) -> Result<(), Error> {
+    encrypt_key_to_file(gpg_home_dir, totp_dir, identifier, key, false)
+}
+
+/// Encrypt and store key to file under name <identifier> in given directory,
+/// overwriting an existing file without prompting.
+pub fn replace_encrypted_key_in_file(
+    gpg_home_dir: &Path,
+    totp_dir: &Path,
+    identifier: &str,
+    key: &str,
+) -> Result<(), Error> {
+    encrypt_key_to_file(gpg_home_dir, totp_dir, identifier, key, true)
+}
+
+/// Encrypt and store the index to its file in the given directory,
+/// overwriting an existing one.
+pub fn write_encrypted_index(
+    gpg_home_dir: &Path,
+    totp_dir: &Path,
+    index: &str,
+) -> Result<(), Error> {
+    encrypt_to_file(
+        gpg_home_dir,
+        totp_dir,
+        &index_file_path(totp_dir),
+        index,
+        true,
+    )
+}
+
+fn encrypt_key_to_file(
+    gpg_home_dir: &Path,
+    totp_dir: &Path,
+    identifier: &str,
+    key: &str,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let new_file_path = key_file_path(totp_dir, identifier)?;
+    encrypt_to_file(gpg_home_dir, totp_dir, &new_file_path, key, overwrite)
+}
+
+fn encrypt_to_file(
+    gpg_home_dir: &Path,
+    totp_dir: &Path,
+    new_file_path: &Path,
+    key: &str,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let gpg_id = resolve_gpg_id(totp_dir, new_file_path)?;
+    if let Some(dir) = new_file_path.parent() {
+        create_dir_all(dir).map_err(|err| Error::io("error creating folder", err))?;
+    }
+    let mut gpg_cmd = Command::new(GPG_COMMAND);
+    if overwrite {
+        gpg_cmd.arg("--yes");
+    }
+    let mut gpg_cmd = spawn_gpg(
+        gpg_cmd
+            .arg("--homedir")
+            .arg(gpg_home_dir)
+            .arg("--encrypt")
+            .arg("--recipient")
+            .arg(gpg_id)
+            .arg("-o")
+            .arg(new_file_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?;
     match gpg_cmd.stdin.take() {
-        None => return Err("Error inputting key to encrypt command".to_string()),
+        None => {
+            return Err(Error::EncryptionFailed(
+                "could not input key to encryption command".to_string(),
+            ))
+        }
         Some(mut stdin) => {
             stdin
                 .write(key.as_bytes())
-                .map_err(|err| format!("Error inputting key to encryption command - {err}"))?;
+                .map_err(|err| Error::io("error inputting key to encryption command", err))?;
         }
     };
-    let exit_status = gpg_cmd.wait().map_err(|err| err.to_string())?;
-    if !exit_status.success() {
-        return Err("Error writing encryted key to file".to_string());
+    let output = gpg_cmd
+        .wait_with_output()
+        .map_err(|err| Error::io(format!("error running command {GPG_COMMAND}"), err))?;
+    if !output.status.success() {
+        return Err(Error::EncryptionFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
     }
     Ok(())
 }
 
 /// Decrypt encrypted key from file with name <identifier> in given directory.
+/// Unless `interactive` is set, fails instead of asking for the passphrase if
+/// GnuPG has not cached it.
 pub fn read_decrypted_key_from_file(
     gpg_home_dir: &Path,
     totp_dir: &Path,
     identifier: &str,
-) -> Result<Option<String>, String> {
-    let gpg_id = read_gpg_id(totp_dir)?;
-    let file_name = format!("{identifier}.{GPG_FILE_EXTENSION}");
-    let file_path = totp_dir.join(file_name);
+    interactive: bool,
+) -> Result<Option<String>, Error> {
+    let file_path = key_file_path(totp_dir, identifier)?;
+    decrypt_file(gpg_home_dir, totp_dir, &file_path, interactive)
+}
+
+/// Decrypt the index from its file in the given directory, or `None` if
+/// there is none. Unless `interactive` is set, fails instead of asking for
+/// the passphrase if GnuPG has not cached it.
+pub fn read_decrypted_index(
+    gpg_home_dir: &Path,
+    totp_dir: &Path,
+    interactive: bool,
+) -> Result<Option<String>, Error> {
+    decrypt_file(
+        gpg_home_dir,
+        totp_dir,
+        &index_file_path(totp_dir),
+        interactive,
+    )
+}
+
+fn decrypt_file(
+    gpg_home_dir: &Path,
+    totp_dir: &Path,
+    file_path: &Path,
+    interactive: bool,
+) -> Result<Option<String>, Error> {
+    let gpg_id = resolve_gpg_id(totp_dir, file_path)?;
     if !file_path.is_file() {
         return Ok(None);
     }
-    let output = Command::new(GPG_COMMAND)
-        .arg("--homedir")
-        .arg(gpg_home_dir)
-        .arg("--recipient")
-        .arg(gpg_id)
-        .arg("--decrypt")
-        .arg(&file_path)
-        .stdout(Stdio::piped())
-        .stdin(Stdio::inherit())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|err| format!("Error running encryption command {GPG_COMMAND} - {err}"))?;
+    let mut gpg_cmd = Command::new(GPG_COMMAND);
+    if !interactive {
+        gpg_cmd.args(["--batch", "--pinentry-mode", "error"]);
+    }
+    let output = spawn_gpg(
+        gpg_cmd
+            .args(["--status-fd", "2"])
+            .arg("--homedir")
+            .arg(gpg_home_dir)
+            .arg("--recipient")
+            .arg(gpg_id)
+            .arg("--decrypt")
+            .arg(file_path)
+            .stdout(Stdio::piped())
+            // gpg asks for the passphrase through pinentry on the terminal,
+            // and must not read stdin, which is the message pipe of the
+            // browser for the native host
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped()),
+    )?
+    .wait_with_output()
+    .map_err(|err| Error::io(format!("error running command {GPG_COMMAND}"), err))?;
     if !output.status.success() {
-        let err_output = String::from_utf8(output.stderr)
-            .map_err(|err| format!("Error parsing {GPG_COMMAND} error message - {err}"))?;
-        return Err(err_output);
+        let (err_output, cancelled) = parse_gpg_error_output(&output.stderr);
+        if cancelled {
+            return Err(Error::DecryptionCancelled);
+        }
+        return Err(Error::DecryptionFailed(err_output));
     }
-    let decrypted_key = String::from_utf8(output.stdout)
-        .map_err(|err| format!("Error reading decrypted key from file - {}", err))?;
+    let decrypted_key = String::from_utf8(output.stdout).map_err(|err| {
+        Error::DecryptionFailed(format!("decrypted key is not valid UTF-8 - {err}"))
+    })?;
     Ok(Some(decrypted_key))
 }
 
-/// Delete file with name <identifier> in given directory.
-pub fn delete_key_file(totp_dir: &Path, identifier: &str) -> Result<(), String> {
-    let file_name = format!("{identifier}.{GPG_FILE_EXTENSION}");
-    let file_path = totp_dir.join(file_name);
-    remove_file(file_path).map_err(|err| format!("Error deleting key - {err}"))
+/// Messages in the error output of GPG run with `--status-fd 2`, and whether
+/// its status lines report that the user cancelled. Unlike the messages, the
+/// status lines do not depend on the locale.
+fn parse_gpg_error_output(stderr: &[u8]) -> (String, bool) {
+    let stderr = String::from_utf8_lossy(stderr);
+    let (status_lines, messages) = stderr
+        .lines()
+        .partition::<Vec<_>, _>(|line| line.starts_with(GPG_STATUS_PREFIX));
+    let cancelled = status_lines.iter().any(|line| {
+        let mut fields = line[GPG_STATUS_PREFIX.len()..].split_whitespace();
+        matches!(fields.next(), Some("ERROR" | "FAILURE"))
+            && fields
+                .nth(1)
+                .and_then(|code| code.parse::<u32>().ok())
+                // the upper bits hold the source of the error
+                .is_some_and(|code| GPG_CANCELLED_ERROR_CODES.contains(&(code & 0xffff)))
+    });
+    (messages.join("\n").trim().to_string(), cancelled)
+}
+
+/// Ids of the primary key and subkeys of the given GPG id in the keyring,
+/// which are empty if the keyring has no key for it.
+pub fn recipient_key_ids(gpg_home_dir: &Path, gpg_id: &str) -> Result<Vec<String>, Error> {
+    let output = spawn_gpg(
+        Command::new(GPG_COMMAND)
+            .arg("--homedir")
+            .arg(gpg_home_dir)
+            .args(["--batch", "--with-colons", "--list-keys", "--"])
+            .arg(gpg_id)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null()),
+    )?
+    .wait_with_output()
+    .map_err(|err| Error::io(format!("error running command {GPG_COMMAND}"), err))?;
+    if !output.status.success() {
+        return Ok(vec![]);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .filter(|fields| matches!(fields[0], "pub" | "sub"))
+        .filter_map(|fields| fields.get(4).map(|key_id| key_id.to_string()))
+        .collect())
+}
+
+/// Ids of the keys the given file is encrypted for, read without decrypting
+/// it. Recipients hidden by the sender have an id of zeros.
+pub fn encrypted_key_ids(gpg_home_dir: &Path, file_path: &Path) -> Result<Vec<String>, Error> {
+    let output = spawn_gpg(
+        Command::new(GPG_COMMAND)
+            .arg("--homedir")
+            .arg(gpg_home_dir)
+            .args(["--batch", "--list-only", "--list-packets", "--"])
+            .arg(file_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?
+    .wait_with_output()
+    .map_err(|err| Error::io(format!("error running command {GPG_COMMAND}"), err))?;
+    if !output.status.success() {
+        return Err(Error::DecryptionFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.starts_with(":pubkey enc packet:"))
+        .filter_map(|line| line.split_once("keyid "))
+        .map(|(_, key_id)| key_id.trim().to_string())
+        .collect())
+}
+
+/// Check if a key file with name <identifier> exists in given directory.
+pub fn key_file_exists(totp_dir: &Path, identifier: &str) -> Result<bool, Error> {
+    Ok(key_file_path(totp_dir, identifier)?.is_file())
+}
+
+/// Delete file with name <identifier> in given directory, and its folders if
+/// they are empty then.
+pub fn delete_key_file(totp_dir: &Path, identifier: &str) -> Result<(), Error> {
+    let file_path = key_file_path(totp_dir, identifier)?;
+    remove_file(&file_path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => Error::EntryNotFound(identifier.to_string()),
+        _ => Error::io("error deleting key", err),
+    })?;
+    remove_empty_folders(totp_dir, &file_path);
+    Ok(())
+}
+
+/// Remove the folders containing the given path in the store directory that
+/// are empty, innermost first. Folders with a `.gpg-id` file are kept.
+fn remove_empty_folders(totp_dir: &Path, path: &Path) {
+    for dir in folders_of(totp_dir, path) {
+        if remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// Move the key file of identifier `source` in `source_dir` to identifier
+/// `target` in `target_dir`, re-encrypting it like [`copy_key_file`].
+pub fn move_key_file(
+    gpg_home_dir: &Path,
+    source_dir: &Path,
+    source: &str,
+    target_dir: &Path,
+    target: &str,
+    force: bool,
+) -> Result<(), Error> {
+    transfer_key_file(
+        gpg_home_dir,
+        source_dir,
+        source,
+        target_dir,
+        target,
+        force,
+        true,
+    )
+}
+
+/// Copy the key file of identifier `source` in `source_dir` to identifier
+/// `target` in `target_dir`. The key is re-encrypted if the GPG ids of their
+/// folders differ. Fails if the target exists, unless `force` is set.
+pub fn copy_key_file(
+    gpg_home_dir: &Path,
+    source_dir: &Path,
+    source: &str,
+    target_dir: &Path,
+    target: &str,
+    force: bool,
+) -> Result<(), Error> {
+    transfer_key_file(
+        gpg_home_dir,
+        source_dir,
+        source,
+        target_dir,
+        target,
+        force,
+        false,
+    )
+}
+
+fn transfer_key_file(
+    gpg_home_dir: &Path,
+    source_dir: &Path,
+    source: &str,
+    target_dir: &Path,
+    target: &str,
+    force: bool,
+    remove_source: bool,
+) -> Result<(), Error> {
+    let source_path = key_file_path(source_dir, source)?;
+    let target_path = key_file_path(target_dir, target)?;
+    if !source_path.is_file() {
+        return Err(Error::EntryNotFound(source.to_string()));
+    }
+    if source_path == target_path {
+        return Err(Error::InvalidArgument(format!(
+            "source and target of {source} are the same"
+        )));
+    }
+    if target_path.is_file() && !force {
+        return Err(Error::EntryExists(target.to_string()));
+    }
+    if resolve_gpg_id(source_dir, &source_path)? == resolve_gpg_id(target_dir, &target_path)? {
+        if let Some(dir) = target_path.parent() {
+            create_dir_all(dir).map_err(|err| Error::io("error creating folder", err))?;
+        }
+        let result = if remove_source {
+            // renaming fails across file systems, copying does not
+            rename(&source_path, &target_path).or_else(|_| {
+                copy(&source_path, &target_path)?;
+                remove_file(&source_path)
+            })
+        } else {
+            copy(&source_path, &target_path).map(|_| ())
+        };
+        result.map_err(|err| Error::io("error writing key", err))?;
+    } else {
+        let key = read_decrypted_key_from_file(gpg_home_dir, source_dir, source, true)?
+            .ok_or_else(|| Error::EntryNotFound(source.to_string()))?;
+        replace_encrypted_key_in_file(gpg_home_dir, target_dir, target, &key)?;
+        if remove_source {
+            remove_file(&source_path).map_err(|err| Error::io("error deleting key", err))?;
+        }
+    }
+    if remove_source {
+        remove_empty_folders(source_dir, &source_path);
+    }
+    Ok(())
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::{
         file::{
             init, list_identifiers, read_decrypted_key_from_file, write_encrypted_key_to_file,
             GPG_FILE_EXTENSION,
         },
-        TOTP_DIR_NAME,
+        Error, TOTP_DIR_NAME,
     };
     use std::{
-        fs::{create_dir, read_to_string, write, OpenOptions},
+        fs::{create_dir, create_dir_all, read_to_string, write, OpenOptions},
         io::{ErrorKind, Write},
         path::Path,
         process::{Command, Stdio},
     };
     use tempfile::{NamedTempFile, TempDir};
 
-    use super::{delete_key_file, GPG_COMMAND, GPG_ID_FILE_NAME};
+    use super::{
+        copy_key_file, delete_key_file, encrypted_key_ids, key_file_exists, key_file_path,
+        move_key_file, parse_gpg_error_output, recipient_key_ids, resolve_gpg_id, GPG_COMMAND,
+        GPG_ID_FILE_NAME,
+    };
 
     const PASSPHRASE: &str = "abc";
 
     fn generate_temp_gpg_key_pair(dir: &Path, gpg_id: &str) {
+        generate_key_pair(dir, gpg_id, &format!("Passphrase: {PASSPHRASE}"));
+    }
+
+    /// Generate a key pair that decrypts without asking for a passphrase.
+    pub(crate) fn generate_temp_gpg_key_pair_without_passphrase(dir: &Path, gpg_id: &str) {
+        generate_key_pair(dir, gpg_id, "%no-protection");
+    }
+
+    fn generate_key_pair(dir: &Path, gpg_id: &str, protection: &str) {
         let config = format!(
             "
             Key-Type: RSA
@@ -179,10 +642,10 @@ mod tests {
             Name-Real: {gpg_id}
             Name-Email: joe@foo.bar
             Expire-Date: 0
-            Passphrase: {PASSPHRASE}
+            {protection}
         "
         );
-        let mut config_file = NamedTempFile::new_in(&dir).unwrap();
+        let mut config_file = NamedTempFile::new_in(dir).unwrap();
         config_file.write_all(config.as_bytes()).unwrap();
 
         let generate_key_pair_output = Command::new(GPG_COMMAND)
@@ -209,7 +672,9 @@ mod tests {
 
         let result = init(&totp_dir, gpg_id);
 
-        assert!(matches!(result, Err(err) if err.contains(gpg_id) ));
+        assert!(
+            matches!(result, Err(Error::AlreadyInitialized { gpg_id: existing_gpg_id, .. }) if existing_gpg_id == gpg_id)
+        );
     }
 
     #[test]
@@ -234,15 +699,15 @@ mod tests {
         // create gpg id file
         let gpg_id = "Test Man";
         init(&totp_dir, gpg_id).unwrap();
-        generate_temp_gpg_key_pair(&dir_path, gpg_id);
+        generate_temp_gpg_key_pair(dir_path, gpg_id);
         let identifier_1 = "test_id_100";
         let identifier_2 = "test_id_1";
         let key_1 = "test_key_1";
         let key_2 = "test_key_2";
-        write_encrypted_key_to_file(&dir_path, &totp_dir, identifier_1, key_1).unwrap();
-        write_encrypted_key_to_file(&dir_path, &totp_dir, identifier_2, key_2).unwrap();
+        write_encrypted_key_to_file(dir_path, &totp_dir, identifier_1, key_1).unwrap();
+        write_encrypted_key_to_file(dir_path, &totp_dir, identifier_2, key_2).unwrap();
 
-        let identifier_list = list_identifiers(&totp_dir).unwrap();
+        let identifier_list = list_identifiers(&totp_dir).unwrap().identifiers;
 
         // list should be ordered by identifier, ascending
         assert_eq!(identifier_list, vec![identifier_2, identifier_1]);
@@ -312,6 +777,254 @@ mod tests {
         assert!(matches!(result, Err(err) if err.kind() == ErrorKind::NotFound));
     }
 
+    #[test]
+    fn key_file_path_rejects_invalid_identifiers() {
+        let totp_dir = Path::new(TOTP_DIR_NAME);
+
+        assert!(key_file_path(totp_dir, "test_identifier").is_ok());
+        assert_eq!(
+            key_file_path(totp_dir, "dir/sub/test_identifier").unwrap(),
+            totp_dir.join("dir").join("sub").join("test_identifier.gpg")
+        );
+        for identifier in [
+            "",
+            ".gpg-id",
+            "../test_identifier",
+            "/test_identifier",
+            "dir/",
+            "dir//test_identifier",
+            ".git/test_identifier",
+            "dir\\test_identifier",
+            "typo:test_identifier",
+        ] {
+            assert!(matches!(
+                key_file_path(totp_dir, identifier),
+                Err(Error::InvalidIdentifier(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn list_includes_identifiers_in_folders() {
+        let dir = TempDir::new().unwrap();
+        create_dir_all(dir.path().join("work").join("aws")).unwrap();
+        create_dir(dir.path().join(".git")).unwrap();
+        write(dir.path().join("personal.gpg"), "").unwrap();
+        write(dir.path().join("work").join("github.gpg"), "").unwrap();
+        write(dir.path().join("work").join("aws").join("root.gpg"), "").unwrap();
+        write(dir.path().join(".git").join("hidden.gpg"), "").unwrap();
+
+        let listing = list_identifiers(dir.path()).unwrap();
+
+        assert_eq!(
+            listing.identifiers,
+            vec!["personal", "work/aws/root", "work/github"]
+        );
+    }
+
+    #[test]
+    fn gpg_id_is_resolved_from_closest_folder() {
+        let dir = TempDir::new().unwrap();
+        let totp_dir = dir.path().join(TOTP_DIR_NAME);
+        init(&totp_dir, "Test Man").unwrap();
+        init(&totp_dir.join("team"), "Team").unwrap();
+        let key_file = |identifier| key_file_path(&totp_dir, identifier).unwrap();
+
+        assert_eq!(
+            resolve_gpg_id(&totp_dir, &key_file("personal")).unwrap(),
+            "Test Man"
+        );
+        assert_eq!(
+            resolve_gpg_id(&totp_dir, &key_file("team/aws")).unwrap(),
+            "Team"
+        );
+        assert_eq!(
+            resolve_gpg_id(&totp_dir, &key_file("team/aws/root")).unwrap(),
+            "Team"
+        );
+        assert_eq!(
+            resolve_gpg_id(&totp_dir, &key_file("other/aws")).unwrap(),
+            "Test Man"
+        );
+    }
+
+    #[test]
+    fn moving_last_key_file_removes_empty_folders() {
+        let dir = TempDir::new().unwrap();
+        let totp_dir = dir.path().join(TOTP_DIR_NAME);
+        init(&totp_dir, "Test Man").unwrap();
+        create_dir_all(totp_dir.join("work").join("aws")).unwrap();
+        write(
+            totp_dir.join("work").join("aws").join("root.gpg"),
+            "encrypted",
+        )
+        .unwrap();
+
+        move_key_file(
+            dir.path(),
+            &totp_dir,
+            "work/aws/root",
+            &totp_dir,
+            "old/root",
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            list_identifiers(&totp_dir).unwrap().identifiers,
+            vec!["old/root"]
+        );
+        assert!(!totp_dir.join("work").exists());
+        delete_key_file(&totp_dir, "old/root").unwrap();
+        assert!(!totp_dir.join("old").exists());
+        assert!(totp_dir.is_dir());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn list_reports_invalid_file_names_as_warnings() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = TempDir::new().unwrap();
+        write(dir.path().join("test_identifier.gpg"), "").unwrap();
+        write(dir.path().join(OsStr::from_bytes(b"invalid\xff.gpg")), "").unwrap();
+        write(dir.path().join("typo:test_identifier.gpg"), "").unwrap();
+
+        let mut listing = list_identifiers(dir.path()).unwrap();
+
+        assert_eq!(listing.identifiers, vec!["test_identifier"]);
+        listing.warnings.sort_by(|a, b| a.message.cmp(&b.message));
+        assert_eq!(listing.warnings.len(), 2);
+        assert_eq!(listing.warnings[0].message, "file name is not valid UTF-8");
+        assert!(listing.warnings[1].message.starts_with("name contains :"));
+    }
+
+    #[test]
+    fn key_file_is_moved_and_copied_within_store() {
+        let dir = TempDir::new().unwrap();
+        let totp_dir = dir.path().join(TOTP_DIR_NAME);
+        init(&totp_dir, "Test Man").unwrap();
+        write(totp_dir.join("source.gpg"), "encrypted").unwrap();
+
+        move_key_file(dir.path(), &totp_dir, "source", &totp_dir, "target", false).unwrap();
+        copy_key_file(dir.path(), &totp_dir, "target", &totp_dir, "copy", false).unwrap();
+
+        assert_eq!(
+            list_identifiers(&totp_dir).unwrap().identifiers,
+            vec!["copy", "target"]
+        );
+        assert_eq!(
+            read_to_string(totp_dir.join("copy.gpg")).unwrap(),
+            "encrypted"
+        );
+    }
+
+    #[test]
+    fn key_file_is_only_overwritten_when_forced() {
+        let dir = TempDir::new().unwrap();
+        let totp_dir = dir.path().join(TOTP_DIR_NAME);
+        init(&totp_dir, "Test Man").unwrap();
+        write(totp_dir.join("source.gpg"), "source").unwrap();
+        write(totp_dir.join("target.gpg"), "target").unwrap();
+
+        let result = copy_key_file(dir.path(), &totp_dir, "source", &totp_dir, "target", false);
+        assert!(matches!(result, Err(Error::EntryExists(_))));
+        assert_eq!(
+            read_to_string(totp_dir.join("target.gpg")).unwrap(),
+            "target"
+        );
+
+        copy_key_file(dir.path(), &totp_dir, "source", &totp_dir, "target", true).unwrap();
+        assert_eq!(
+            read_to_string(totp_dir.join("target.gpg")).unwrap(),
+            "source"
+        );
+    }
+
+    #[test]
+    fn cancel_is_detected_from_gpg_status_lines() {
+        let cancelled = "[GNUPG:] ENC_TO 77156DB58C1B612E 1 0
+gpg: Entschlüsselung mit dem öffentlichen Schlüssel fehlgeschlagen: Abbruch
+[GNUPG:] ERROR pkdecrypt_failed 83886179
+gpg: Entschlüsselung fehlgeschlagen: Kein geheimer Schlüssel";
+        let no_pinentry = "gpg: public key decryption failed: No pinentry
+[GNUPG:] ERROR pkdecrypt_failed 67108949
+gpg: decryption failed: No secret key (cancelled)";
+
+        assert_eq!(
+            parse_gpg_error_output(cancelled.as_bytes()),
+            (
+                "gpg: Entschlüsselung mit dem öffentlichen Schlüssel fehlgeschlagen: Abbruch
+gpg: Entschlüsselung fehlgeschlagen: Kein geheimer Schlüssel"
+                    .to_string(),
+                true
+            )
+        );
+        assert!(!parse_gpg_error_output(no_pinentry.as_bytes()).1);
+    }
+
+    #[test]
+    fn key_file_is_re_encrypted_for_gpg_id_of_target() {
+        let dir = TempDir::new().unwrap();
+        let gnupg_home = dir.path();
+        generate_temp_gpg_key_pair_without_passphrase(gnupg_home, "Test Man");
+        generate_temp_gpg_key_pair_without_passphrase(gnupg_home, "Other Man");
+        let test_man_key_ids = recipient_key_ids(gnupg_home, "Test Man").unwrap();
+        let other_man_key_ids = recipient_key_ids(gnupg_home, "Other Man").unwrap();
+        let personal_dir = dir.path().join("personal");
+        let team_dir = dir.path().join("team");
+        init(&personal_dir, "Test Man").unwrap();
+        init(&team_dir, "Other Man").unwrap();
+        init(&personal_dir.join("shared"), "Other Man").unwrap();
+        write_encrypted_key_to_file(gnupg_home, &personal_dir, "example", "key").unwrap();
+        let encrypted_for = |totp_dir: &Path, identifier| {
+            let path = key_file_path(totp_dir, identifier).unwrap();
+            encrypted_key_ids(gnupg_home, &path).unwrap()
+        };
+        assert!(test_man_key_ids.contains(&encrypted_for(&personal_dir, "example")[0]));
+
+        move_key_file(
+            gnupg_home,
+            &personal_dir,
+            "example",
+            &team_dir,
+            "moved",
+            false,
+        )
+        .unwrap();
+        copy_key_file(
+            gnupg_home,
+            &team_dir,
+            "moved",
+            &personal_dir,
+            "copied",
+            false,
+        )
+        .unwrap();
+        move_key_file(
+            gnupg_home,
+            &personal_dir,
+            "copied",
+            &personal_dir,
+            "shared/example",
+            false,
+        )
+        .unwrap();
+
+        for (totp_dir, identifier, key_ids) in [
+            (&team_dir, "moved", &other_man_key_ids),
+            (&personal_dir, "shared/example", &other_man_key_ids),
+        ] {
+            let encrypted_for = encrypted_for(totp_dir, identifier);
+            assert!(!encrypted_for.is_empty());
+            assert!(encrypted_for.iter().all(|key_id| key_ids.contains(key_id)));
+            let key = read_decrypted_key_from_file(gnupg_home, totp_dir, identifier, true).unwrap();
+            assert_eq!(key.as_deref(), Some("key"));
+        }
+        assert!(!key_file_exists(&personal_dir, "example").unwrap());
+        assert!(!key_file_exists(&personal_dir, "copied").unwrap());
+    }
+
     #[test]
     #[ignore = "requires manual input"]
     fn read_key_from_file_and_decrypt_manual() {
@@ -328,7 +1041,7 @@ mod tests {
         let key = "1234567890";
         write_encrypted_key_to_file(&dir_path, &totp_dir, identifier, key).unwrap();
 
-        let decrypted_key = read_decrypted_key_from_file(&dir_path, &totp_dir, identifier)
+        let decrypted_key = read_decrypted_key_from_file(&dir_path, &totp_dir, identifier, true)
             .unwrap()
             .unwrap();
         assert_eq!(decrypted_key, key);
@@ -0,0 +1,107 @@
+use qrcode::{render::unicode::Dense1x2, QrCode};
+
+use crate::{entry::Entry, error::Error};
+
+/// Supported key lengths in bits.
+pub const KEY_BITS: [usize; 3] = [160, 256, 512];
+/// Default key length in bits, as recommended by RFC 4226.
+pub const DEFAULT_KEY_BITS: usize = 160;
+
+/// Generate a random key of the given length in bits from the operating
+/// system's secure random number generator.
+pub fn generate_key(bits: usize) -> Result<Vec<u8>, Error> {
+    if !KEY_BITS.contains(&bits) {
+        return Err(Error::InvalidArgument(format!(
+            "unsupported key length {bits} - must be one of {KEY_BITS:?} bits"
+        )));
+    }
+    let mut key = vec![0; bits / 8];
+    getrandom::getrandom(&mut key)
+        .map_err(|error| Error::Other(format!("error generating random key - {error}")))?;
+    Ok(key)
+}
+
+/// Build the otpauth URI for provisioning the given entry in authenticator
+/// apps. The label is the issuer and account of the entry if set, or the
+/// identifier otherwise.
+pub fn otpauth_uri(identifier: &str, entry: &Entry) -> String {
+    let metadata = &entry.metadata;
+    let label = match (&metadata.issuer, &metadata.account) {
+        (Some(issuer), Some(account)) => {
+            format!("{}:{}", percent_encode(issuer), percent_encode(account))
+        }
+        (_, Some(account)) => percent_encode(account),
+        (_, None) => percent_encode(identifier),
+    };
+    let mut uri = format!("otpauth://totp/{label}?secret={}", entry.secret);
+    if let Some(issuer) = &metadata.issuer {
+        uri.push_str(&format!("&issuer={}", percent_encode(issuer)));
+    }
+    uri.push_str(&format!(
+        "&algorithm={}&digits={}&period={}",
+        entry.algorithm, entry.digits, entry.period
+    ));
+    uri
+}
+
+/// Render the given text as QR code for output in a terminal.
+pub fn qr_code(text: &str) -> Result<String, Error> {
+    let code = QrCode::new(text.as_bytes())
+        .map_err(|error| Error::Other(format!("error generating QR code - {error}")))?;
+    Ok(code.render::<Dense1x2>().quiet_zone(true).build())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compute::Algorithm,
+        entry::Entry,
+        generate::{generate_key, otpauth_uri},
+    };
+
+    #[test]
+    fn generate_key_of_given_length() {
+        assert_eq!(generate_key(160).unwrap().len(), 20);
+        assert_eq!(generate_key(512).unwrap().len(), 64);
+        assert!(generate_key(100).is_err());
+    }
+
+    #[test]
+    fn otpauth_uri_encodes_label() {
+        let uri = otpauth_uri("Example:alice@example.com", &Entry::new("JBSWY3DPEHPK3PXP"));
+
+        assert_eq!(
+            uri,
+            "otpauth://totp/Example%3Aalice%40example.com?secret=JBSWY3DPEHPK3PXP&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn otpauth_uri_contains_entry_parameters() {
+        let mut entry = Entry::new("JBSWY3DPEHPK3PXP");
+        entry.algorithm = Algorithm::Sha512;
+        entry.digits = 8;
+        entry.period = 60;
+        entry.metadata.issuer = Some("Example Co".to_string());
+        entry.metadata.account = Some("alice@example.com".to_string());
+
+        let uri = otpauth_uri("example", &entry);
+
+        assert_eq!(
+            uri,
+            "otpauth://totp/Example%20Co:alice%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example%20Co&algorithm=SHA512&digits=8&period=60"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    ffi::OsStr,
+    fs::{create_dir_all, read_to_string, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+    process::{Command, Output},
+};
+
+use crate::error::Error;
+
+const GIT_COMMAND: &str = "git";
+pub const GIT_DIR_NAME: &str = ".git";
+const GIT_EXCLUDE_FILE: &str = "info/exclude";
+
+/// Commit the changes of the given files in the git repository of the store
+/// directory. Does nothing if the store directory is not a git repository.
+pub fn commit(store_dir: &Path, paths: &[&Path], message: &str) -> Result<(), Error> {
+    if !store_dir.join(GIT_DIR_NAME).exists() {
+        return Ok(());
+    }
+    for path in paths {
+        let args: &[&str] = if path.exists() {
+            &["add", "--"]
+        } else {
+            &["rm", "--cached", "--ignore-unmatch", "--quiet", "--"]
+        };
+        run_git(
+            store_dir,
+            args.iter().map(OsStr::new).chain([path.as_os_str()]),
+        )?;
+    }
+    // nothing is staged if only untracked files were removed
+    let staged = git(store_dir, ["diff", "--cached", "--quiet"])?;
+    if staged.status.success() {
+        return Ok(());
+    }
+    run_git(store_dir, ["commit", "--quiet", "--message", message])
+}
+
+/// Exclude the file of the given name in the store directory from the git
+/// repository of the store directory, locally in `.git/info/exclude` so that
+/// it is not shared. Does nothing if the store directory is not a git
+/// repository or the file is excluded already.
+pub fn exclude(store_dir: &Path, file_name: &str) -> Result<(), Error> {
+    let git_dir = store_dir.join(GIT_DIR_NAME);
+    if !git_dir.is_dir() {
+        return Ok(());
+    }
+    let exclude_file = git_dir.join(GIT_EXCLUDE_FILE);
+    let pattern = format!("/{file_name}");
+    let excluded = match read_to_string(&exclude_file) {
+        Ok(patterns) => patterns,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(Error::io("error reading git exclude file", err)),
+    };
+    if excluded.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+    let separator = if excluded.is_empty() || excluded.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    if let Some(info_dir) = exclude_file.parent() {
+        create_dir_all(info_dir).map_err(|err| Error::io("error creating git info dir", err))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&exclude_file)
+        .and_then(|mut file| writeln!(file, "{separator}{pattern}"))
+        .map_err(|err| Error::io("error writing git exclude file", err))
+}
+
+fn git(
+    store_dir: &Path,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+) -> Result<Output, Error> {
+    Command::new(GIT_COMMAND)
+        .arg("-C")
+        .arg(store_dir)
+        .args(args)
+        .output()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => Error::Other(format!(
+                "store is a git repository, but {GIT_COMMAND} is not installed"
+            )),
+            _ => Error::io(format!("error running command {GIT_COMMAND}"), err),
+        })
+}
+
+fn run_git(
+    store_dir: &Path,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+) -> Result<(), Error> {
+    let output = git(store_dir, args)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => stderr.to_string(),
+        };
+        return Err(Error::Other(format!(
+            "error running command {GIT_COMMAND} - {reason}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{read_to_string, write},
+        path::Path,
+        process::Command,
+    };
+
+    use tempfile::TempDir;
+
+    use crate::git::{commit, exclude};
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn commit_records_changes_in_repository() {
+        let dir = TempDir::new().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        git(dir.path(), &["config", "user.name", "Test Man"]);
+        git(dir.path(), &["config", "user.email", "joe@foo.bar"]);
+        let path = dir.path().join("test_identifier.gpg");
+        write(&path, "encrypted").unwrap();
+
+        commit(dir.path(), &[&path], "Add test_identifier").unwrap();
+
+        assert_eq!(
+            git(dir.path(), &["log", "--format=%s"]).trim(),
+            "Add test_identifier"
+        );
+        assert_eq!(git(dir.path(), &["ls-files"]).trim(), "test_identifier.gpg");
+    }
+
+    #[test]
+    fn commit_ignores_directory_without_repository() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test_identifier.gpg");
+
+        assert!(commit(dir.path(), &[&path], "Add test_identifier").is_ok());
+    }
+
+    #[test]
+    fn exclude_hides_file_from_repository_once() {
+        let dir = TempDir::new().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        write(dir.path().join(".index.gpg"), "encrypted").unwrap();
+
+        exclude(dir.path(), ".index.gpg").unwrap();
+        exclude(dir.path(), ".index.gpg").unwrap();
+
+        let status = git(dir.path(), &["status", "--porcelain", "--ignored"]);
+        assert_eq!(status.trim(), "!! .index.gpg");
+        let excluded = read_to_string(dir.path().join(".git/info/exclude")).unwrap();
+        assert_eq!(excluded.matches("/.index.gpg").count(), 1);
+    }
+}
@@ -0,0 +1,348 @@
+//! Encrypted index of the entries of a store, so that their metadata can be
+//! listed and searched with one decryption instead of one per entry.
+//!
+//! The index is the file `.index.gpg` in the store directory, encrypted for
+//! the GPG id of the store like its entries. It holds a TOML document:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [entries.github]
+//! issuer = "GitHub"
+//! account = "alice"
+//! tags = ["work"]
+//! algorithm = "SHA1"
+//! last_used = "2026-10-18T12:00:00Z"
+//! ```
+//!
+//! A store has no index until `totpc index` builds it. From then on, it is
+//! updated whenever an entry is written or deleted, and whenever a password
+//! is computed if `record_last_used` is set in the config file. The key files
+//! remain the source of truth for which identifiers exist; the index only adds
+//! their metadata, so entries missing from it are decrypted instead. Entries
+//! in folders with a GPG id of their own are never indexed, as everyone who
+//! can decrypt the index could read their metadata.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute::Algorithm,
+    entry::Entry,
+    error::Error,
+    error_reason,
+    file::{
+        index_file_path, key_file_path, read_decrypted_index, read_gpg_id, resolve_gpg_id,
+        write_encrypted_index, Warning,
+    },
+    git::exclude,
+    store::Store,
+};
+
+/// Current version of the index format.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Metadata of an entry kept in the index.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IndexEntry {
+    /// Provider of the account.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Name of the account at the issuer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Tags of the entry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Hash algorithm of the entry.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// Time the password was last computed as RFC 3339 timestamp.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<String>,
+}
+
+impl IndexEntry {
+    /// Index entry of the given entry, which has not been used yet.
+    pub fn new(entry: &Entry) -> Self {
+        Self {
+            issuer: entry.metadata.issuer.clone(),
+            account: entry.metadata.account.clone(),
+            tags: entry.metadata.tags.clone(),
+            algorithm: entry.algorithm,
+            last_used: None,
+        }
+    }
+}
+
+/// Index entries by identifier.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Index {
+    /// Version of the index format.
+    pub version: u32,
+    /// Entries by identifier, without store prefix.
+    #[serde(default)]
+    pub entries: BTreeMap<String, IndexEntry>,
+}
+
+impl Index {
+    /// Parse a decrypted index.
+    pub fn parse(payload: &str) -> Result<Self, Error> {
+        let index = toml::from_str::<Self>(payload).map_err(|error| {
+            Error::Other(format!(
+                "invalid index - {} - rebuild it with totpc index",
+                error.message()
+            ))
+        })?;
+        if !(1..=INDEX_VERSION).contains(&index.version) {
+            return Err(Error::Other(format!(
+                "unsupported index version {} - update totpc",
+                index.version
+            )));
+        }
+        Ok(index)
+    }
+
+    /// Payload of the index in the current format.
+    pub fn to_payload(&self) -> Result<String, Error> {
+        toml::to_string(&Self {
+            version: INDEX_VERSION,
+            ..self.clone()
+        })
+        .map_err(|error| Error::Other(format!("error serializing index - {error}")))
+    }
+
+    /// Set the index entry of the identifier to the given entry, keeping the
+    /// time it was last used.
+    pub fn set(&mut self, identifier: &str, entry: &Entry) {
+        let last_used = self
+            .entries
+            .remove(identifier)
+            .and_then(|previous| previous.last_used);
+        self.entries.insert(
+            identifier.to_string(),
+            IndexEntry {
+                last_used,
+                ..IndexEntry::new(entry)
+            },
+        );
+    }
+}
+
+/// Decrypt the index of the store directory, or `None` if it has none.
+/// Unless `interactive` is set, fails instead of asking for the passphrase.
+pub fn read_index(
+    gnupg_home: &Path,
+    store_dir: &Path,
+    interactive: bool,
+) -> Result<Option<Index>, Error> {
+    read_decrypted_index(gnupg_home, store_dir, interactive)?
+        .map(|payload| Index::parse(&payload))
+        .transpose()
+}
+
+/// Apply the change to the index of the store directory and encrypt it
+/// again. Does nothing if the store has no index.
+pub fn update_index(
+    gnupg_home: &Path,
+    store_dir: &Path,
+    change: impl FnOnce(&mut Index),
+) -> Result<(), Error> {
+    let Some(mut index) = read_index(gnupg_home, store_dir, true)? else {
+        return Ok(());
+    };
+    change(&mut index);
+    let gpg_id = read_gpg_id(store_dir)?;
+    index
+        .entries
+        .retain(|identifier, _| is_indexable(store_dir, &gpg_id, identifier));
+    write_encrypted_index(gnupg_home, store_dir, &index.to_payload()?)
+}
+
+/// Whether the entry of the identifier is encrypted for the given GPG id of
+/// the store, like the index. Entries in folders with a GPG id of their own
+/// are left out, so that their metadata is only readable with their key.
+fn is_indexable(store_dir: &Path, gpg_id: &str, identifier: &str) -> bool {
+    key_file_path(store_dir, identifier)
+        .and_then(|path| resolve_gpg_id(store_dir, &path))
+        .is_ok_and(|entry_gpg_id| entry_gpg_id == gpg_id)
+}
+
+/// Apply the change to the index after entries of the store directory were
+/// written or deleted. An index that cannot be updated, e. g. because the
+/// passphrase was not entered, is left as it is and reported as warning, until
+/// `totpc index` rebuilds it.
+pub fn reindex(
+    gnupg_home: &Path,
+    store_dir: &Path,
+    change: impl FnOnce(&mut Index),
+) -> Option<Warning> {
+    let error = update_index(gnupg_home, store_dir, change).err()?;
+    Some(Warning {
+        path: Some(index_file_path(store_dir)),
+        message: format!(
+            "not updated - {} - rebuild it with totpc index",
+            error_reason(&error)
+        ),
+    })
+}
+
+/// Decrypt all entries of the store directory that are encrypted for its GPG
+/// id and write a new index of them, keeping the times they were last used
+/// from the previous index if it can be read. Entries that fail to decrypt are
+/// left out and reported as warnings. The index is excluded from the git repository of the store, as
+/// it may change whenever a password is computed.
+pub fn rebuild_index(gnupg_home: &Path, store_dir: &Path) -> Result<(Index, Vec<Warning>), Error> {
+    let store = Store::open(store_dir, gnupg_home)?;
+    let previous = read_index(gnupg_home, store_dir, true)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let gpg_id = store.gpg_id()?;
+    let listing = store.list()?;
+    let mut warnings = listing.warnings;
+    let mut index = Index::default();
+    for identifier in listing
+        .identifiers
+        .into_iter()
+        .filter(|identifier| is_indexable(store_dir, &gpg_id, identifier))
+    {
+        match store.get(&identifier) {
+            Ok(entry) => {
+                let last_used = previous
+                    .entries
+                    .get(&identifier)
+                    .and_then(|previous| previous.last_used.clone());
+                let indexed = IndexEntry {
+                    last_used,
+                    ..IndexEntry::new(&entry)
+                };
+                index.entries.insert(identifier, indexed);
+            }
+            Err(error) => warnings.push(Warning {
+                path: key_file_path(store_dir, &identifier).ok(),
+                message: format!("not indexed - {}", error_reason(&error)),
+            }),
+        }
+    }
+    write_encrypted_index(gnupg_home, store_dir, &index.to_payload()?)?;
+    if let Some(file_name) = index_file_path(store_dir).file_name() {
+        exclude(store_dir, &file_name.to_string_lossy())?;
+    }
+    Ok((index, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read, write};
+
+    use tempfile::TempDir;
+
+    use crate::{
+        compute::Algorithm,
+        entry::Entry,
+        file::{
+            index_file_path, init, tests::generate_temp_gpg_key_pair_without_passphrase,
+            write_encrypted_key_to_file,
+        },
+        index::{read_index, rebuild_index, reindex, Index, INDEX_VERSION},
+        Error,
+    };
+
+    #[test]
+    fn index_is_written_and_read() {
+        let mut entry = Entry::new("JBSWY3DPEHPK3PXP");
+        entry.algorithm = Algorithm::Sha256;
+        entry.metadata.issuer = Some("Example".to_string());
+        entry.metadata.tags = vec!["prod".to_string()];
+        let mut index = Index::default();
+        index.set("example", &entry);
+
+        let payload = index.to_payload().unwrap();
+
+        assert!(payload.starts_with(&format!("version = {INDEX_VERSION}\n")));
+        let parsed = Index::parse(&payload).unwrap();
+        assert_eq!(parsed.entries, index.entries);
+        assert_eq!(parsed.entries["example"].algorithm, Algorithm::Sha256);
+    }
+
+    #[test]
+    fn set_keeps_last_use() {
+        let mut index = Index::default();
+        index.set("example", &Entry::new("JBSWY3DPEHPK3PXP"));
+        index.entries.get_mut("example").unwrap().last_used =
+            Some("2026-10-18T12:00:00Z".to_string());
+        let mut entry = Entry::new("JBSWY3DPEHPK3PXP");
+        entry.metadata.account = Some("alice".to_string());
+
+        index.set("example", &entry);
+
+        assert_eq!(index.entries["example"].account.as_deref(), Some("alice"));
+        assert_eq!(
+            index.entries["example"].last_used.as_deref(),
+            Some("2026-10-18T12:00:00Z")
+        );
+    }
+
+    #[test]
+    fn unsupported_index_fails() {
+        for payload in [
+            "version = 2",
+            "entries = 1",
+            "version = 1\n[entries.a]\ntags = 1",
+        ] {
+            assert!(
+                matches!(Index::parse(payload), Err(Error::Other(_))),
+                "{payload}"
+            );
+        }
+    }
+
+    #[test]
+    fn index_that_cannot_be_updated_is_kept_with_warning() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path().join(".gpg-id"), "Test Man").unwrap();
+        let index_file = index_file_path(dir.path());
+        write(&index_file, "not encrypted").unwrap();
+
+        let warning = reindex(dir.path(), dir.path(), |index| {
+            index.entries.clear();
+        })
+        .unwrap();
+
+        assert_eq!(warning.path.as_ref(), Some(&index_file));
+        assert!(warning.message.starts_with("not updated - "));
+        assert_eq!(read(&index_file).unwrap(), b"not encrypted");
+    }
+
+    #[test]
+    fn entries_of_folders_with_other_gpg_id_are_not_indexed() {
+        let dir = TempDir::new().unwrap();
+        let store_dir = dir.path().join("store");
+        generate_temp_gpg_key_pair_without_passphrase(dir.path(), "Test Man");
+        generate_temp_gpg_key_pair_without_passphrase(dir.path(), "Other Man");
+        init(&store_dir, "Test Man").unwrap();
+        init(&store_dir.join("team"), "Other Man").unwrap();
+        init(&store_dir.join("own"), "Test Man").unwrap();
+        for identifier in ["personal", "own/work", "team/shared"] {
+            write_encrypted_key_to_file(dir.path(), &store_dir, identifier, "JBSWY3DPEHPK3PXP")
+                .unwrap();
+        }
+
+        let (index, warnings) = rebuild_index(dir.path(), &store_dir).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            index.entries.keys().collect::<Vec<_>>(),
+            ["own/work", "personal"]
+        );
+
+        let entry = Entry::new("JBSWY3DPEHPK3PXP");
+        let warning = reindex(dir.path(), &store_dir, |index| {
+            index.set("team/other", &entry)
+        });
+        assert!(warning.is_none(), "{warning:?}");
+        let index = read_index(dir.path(), &store_dir, true).unwrap().unwrap();
+        assert!(!index.entries.contains_key("team/other"));
+    }
+}
@@ -1,24 +1,85 @@
-use std::path::Path;
-use std::{fmt::Display, io::stdin};
+//! TOTP computer - time-based one time passwords with GPG encrypted keys.
+//!
+//! The command line tool `totpc` is built on [`run`]. Rust programs can embed
+//! the key store with [`Store`] and compute passwords with [`Totp`]; neither
+//! prints to stdout or stderr.
+//!
+//! ```no_run
+//! use totpc::Store;
+//!
+//! let store = Store::open("/home/alice/.totpc", "/home/alice/.gnupg")?;
+//! let totp = store.get("example")?.totp()?;
+//! let time = 1111111109;
+//! println!("{} (valid for {}s)", totp.generate_at(time)?, totp.valid_for(time));
+//! # Ok::<(), totpc::Error>(())
+//! ```
 
-use compute::compute;
+use std::{
+    collections::HashMap,
+    io::{stdin, stdout},
+    iter::once,
+    path::{Path, PathBuf},
+};
+
+use completion::{complete, script};
+use compute::{seconds_remaining, TIME_STEP_INTERVAL};
+use entry::{parse_secret, parse_tag};
 use file::{
-    delete_key_file, init, list_identifiers, read_decrypted_key_from_file,
-    write_encrypted_key_to_file,
+    copy_key_file, delete_key_file, folder_path, init, key_file_exists, key_file_path,
+    list_identifiers, move_key_file, replace_encrypted_key_in_file, write_encrypted_key_to_file,
+    GPG_ID_FILE_NAME,
 };
+use generate::{generate_key, otpauth_uri, qr_code};
+use git::commit;
+use index::{read_index, rebuild_index, reindex, update_index, IndexEntry};
+use menu::{clear_later, pick, send, Tools};
+use output::{FoundEntry, ListedEntry, Output, StoreEntries, TotpEntry};
+use search::rank_identifier;
 
-use crate::base32::decode;
+pub use cli::{Cli, Command, TagAction};
+pub use completion::Shell;
+pub use compute::Algorithm;
+pub use config::{Config, Settings, ENV_CONFIG, ENV_PROFILE};
+pub use dirs::{Dirs, Source, ENV_DIR, ENV_GNUPG_HOME, ENV_STORE_DIR, STORE_SEPARATOR};
+pub use edit::{edit_in_editor, editor};
+pub use entry::{Entry, Metadata, ENTRY_VERSION};
+pub use error::Error;
+pub use file::{Listing, Warning};
+pub use output::{render_error, Format};
+pub use store::Store;
+pub use totp::Totp;
 
+use crate::base32::encode;
+use crate::time::{apply_offset, format_rfc3339};
+
+#[cfg(unix)]
+mod agent;
 mod base32;
+mod check;
+mod cli;
+mod completion;
 mod compute;
+mod config;
+mod dirs;
+mod edit;
+mod entry;
+mod error;
 mod file;
+mod generate;
+mod git;
+mod index;
+mod menu;
+mod native_host;
+mod output;
+mod search;
+mod store;
+mod time;
+mod totp;
+mod tui;
 
 /// Default totpc directory.
 pub const TOTP_DIR_NAME: &str = ".totpc";
 
-const BIN_COMMAND: &str = "totpc";
-/// Help sub command.
-pub const COMMAND_HELP: &str = "--help";
 /// Init command.
 pub const COMMAND_INIT: &str = "init";
 /// Init command shortcut.
@@ -41,210 +102,945 @@ pub const COMMAND_DELETE: &str = "delete";
 pub const COMMAND_LIST: &str = "list";
 /// List command shortcut.
 pub const COMMAND_SHORT_LIST: &str = "l";
+/// Generate command.
+pub const COMMAND_GENERATE: &str = "generate";
+/// Generate command shortcut.
+pub const COMMAND_SHORT_GENERATE: &str = "g";
+/// Verify command.
+pub const COMMAND_VERIFY: &str = "verify";
+/// Verify command shortcut.
+pub const COMMAND_SHORT_VERIFY: &str = "v";
+/// Move command.
+pub const COMMAND_MOVE: &str = "mv";
+/// Copy command.
+pub const COMMAND_COPY: &str = "cp";
+/// Edit command.
+pub const COMMAND_EDIT: &str = "edit";
+/// Edit command shortcut.
+pub const COMMAND_SHORT_EDIT: &str = "e";
+/// Tag command.
+pub const COMMAND_TAG: &str = "tag";
+/// Index command.
+pub const COMMAND_INDEX: &str = "index";
+/// Check command.
+pub const COMMAND_CHECK: &str = "check";
+/// Find command.
+pub const COMMAND_FIND: &str = "find";
+/// Menu command.
+pub const COMMAND_MENU: &str = "menu";
+/// Terminal interface command.
+pub const COMMAND_TUI: &str = "tui";
+/// Agent command.
+pub const COMMAND_AGENT: &str = "agent";
+/// Native messaging host command.
+pub const COMMAND_NATIVE_HOST: &str = "native-host";
+/// Completions command.
+pub const COMMAND_COMPLETIONS: &str = "completions";
 
-const IDENTIFIER_LIST_HEADER: &str = "totp computer\n";
-const IDENTIFIER_LIST_ITEM_PREFIX: &str = "├─";
-const IDENTIFIER_LIST_LAST_ITEM_PREFIX: &str = "└─";
-
-/// Message to display when command fails.
-pub enum ErrorMessage<'a> {
-    EmptyKey,
-    MissingIdentifier(&'a str),
-}
-
-impl Display for ErrorMessage<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::EmptyKey => f.write_str("Error: key must not be empty"),
-            Self::MissingIdentifier(command) => f.write_str(&format!(
-                "Error: missing identifier - specify the identifier to {command}"
-            )),
-        }
-    }
-}
-
-impl From<ErrorMessage<'_>> for String {
-    fn from(value: ErrorMessage) -> Self {
-        value.to_string()
-    }
-}
-
-/// Returns the general help text.
-pub fn get_help_text() -> String {
-    format!(
-        "TOTP Computer - time-based one time password computer
+/// User interaction that commands need, provided by the front-end, so that
+/// the library itself never reads from or writes to the terminal.
+pub trait Interaction {
+    /// Read the Base32 encoded key to store for the given identifier.
+    fn read_key(&mut self, identifier: &str) -> Result<String, Error>;
 
-Usage:
-    {BIN_COMMAND} [{COMMAND_INIT}, {COMMAND_SHORT_INIT}] <gpg-id>
-        Initialize totp computer with gpg-id for encrypting keys.
+    /// Let the user edit the given decrypted entry payload and return the
+    /// edited payload, e. g. with [`edit_in_editor`].
+    fn edit(&mut self, payload: &str) -> Result<String, Error>;
 
-    {BIN_COMMAND} [{COMMAND_LIST}, {COMMAND_SHORT_LIST}]
-        List all stored identifiers.
-
-    {BIN_COMMAND} [{COMMAND_COMPUTE}, {COMMAND_SHORT_COMPUTE}] <identifier>
-        Compute current one time password for given identifier.
-
-    {BIN_COMMAND} {COMMAND_DELETE} <identifier>
-        Delete identifier and key from store.
-
-    {BIN_COMMAND} [{COMMAND_LOAD}, {COMMAND_SHORT_LOAD}] <identifier>
-        Decrypt and output key of given identifier.
-
-    {BIN_COMMAND} [{COMMAND_SAVE}, {COMMAND_SHORT_SAVE}] <identifier>
-        Save key for given identifier.
-        Prompts to overwrite existing files."
-    )
+    /// Let the user choose one of several identifiers that match the given
+    /// pattern equally well.
+    fn choose(&mut self, pattern: &str, identifiers: &[String]) -> Result<String, Error>;
 }
 
-/// Identifies entered command and calls corresponding function.
+/// Calls the function corresponding to the given command and renders the
+/// output in the given format. New entries get the digits and period of the
+/// given settings unless set on the command line.
 ///
 /// # Errors
 ///
-/// Returns error when command is unknown.
-pub fn run(gpg_home_dir: &Path, totp_dir: &Path, args: Vec<String>) -> Result<String, String> {
-    let command = {
-        if args.len() < 2 {
-            COMMAND_LIST
-        } else {
-            args[1].as_str()
+/// Returns error when the command fails. Fails with [`Error::ComputeFailed`],
+/// which holds the rendered output, if `compute --all` failed for some entries.
+pub fn run(
+    dirs: &Dirs,
+    settings: &Settings,
+    format: Format,
+    command: Command,
+    interaction: &mut dyn Interaction,
+) -> Result<String, Error> {
+    let output = run_command(dirs, settings, command, interaction)?;
+    let rendered = output.render(format)?;
+    match output {
+        Output::TotpTable { entries, .. } if entries.iter().any(|entry| entry.error.is_some()) => {
+            Err(Error::ComputeFailed {
+                output: rendered,
+                failed: entries.iter().filter(|entry| entry.error.is_some()).count(),
+                total: entries.len(),
+            })
         }
-    };
+        _ => Ok(rendered),
+    }
+}
 
+fn run_command(
+    dirs: &Dirs,
+    settings: &Settings,
+    command: Command,
+    interaction: &mut dyn Interaction,
+) -> Result<Output, Error> {
+    let gpg_home_dir = dirs.gnupg_home.as_path();
+    let totp_dir = dirs.store_dir.as_path();
     match command {
-        COMMAND_INIT | COMMAND_SHORT_INIT => {
-            if args.len() < 3 {
-                return Err(
-                    "Error: gpg id required for initialization - totp init <gpg_id>".to_string(),
-                );
+        Command::Init { gpg_id, folder } => {
+            let mut warnings = vec![];
+            let (store_dir, store_dir_source) = match &folder {
+                None => {
+                    init(totp_dir, &gpg_id)?;
+                    let path = totp_dir.join(GPG_ID_FILE_NAME);
+                    let message = format!("Initialize for {gpg_id}");
+                    warnings.extend(commit_change(totp_dir, &[&path], &message));
+                    (dirs.store_dir.clone(), dirs.store_dir_source)
+                }
+                Some(folder) => {
+                    let (store_dir, name) = dirs.locate(folder);
+                    let folder_dir = folder_path(store_dir, name)?;
+                    init(&folder_dir, &gpg_id)?;
+                    let path = folder_dir.join(GPG_ID_FILE_NAME);
+                    let message = format!("Initialize {folder} for {gpg_id}");
+                    warnings.extend(commit_change(store_dir, &[&path], &message));
+                    match store_dir == totp_dir {
+                        true => (dirs.store_dir.clone(), dirs.store_dir_source),
+                        false => (store_dir.to_path_buf(), Source::Config),
+                    }
+                }
+            };
+            Ok(Output::Initialized {
+                gpg_id,
+                folder,
+                store_dir,
+                store_dir_source,
+                gnupg_home: dirs.gnupg_home.clone(),
+                gnupg_home_source: dirs.gnupg_home_source,
+                warnings,
+            })
+        }
+        Command::Find { pattern, metadata } => {
+            let (identifiers, _) = list_all_identifiers(dirs)?;
+            let identifiers = identifiers.into_iter().flatten().collect::<Vec<_>>();
+            let mut index_entries = match metadata {
+                true => index_entries(dirs, &identifiers)?,
+                false => vec![],
+            }
+            .into_iter();
+            let mut matches = identifiers
+                .into_iter()
+                .filter_map(|identifier| {
+                    let mut found = FoundEntry {
+                        identifier,
+                        issuer: None,
+                        account: None,
+                    };
+                    // Entries that fail to decrypt match by identifier only.
+                    if let Some(Ok(indexed)) = index_entries.next() {
+                        found.issuer = indexed.issuer;
+                        found.account = indexed.account;
+                    }
+                    let texts = [&found.issuer, &found.account].into_iter().flatten();
+                    let rank = texts
+                        .filter_map(|text| search::rank(&pattern, text))
+                        .chain(rank_identifier(&pattern, &found.identifier))
+                        .min()?;
+                    Some((rank, found))
+                })
+                .collect::<Vec<_>>();
+            matches.sort_by(|(rank, found), (other_rank, other)| {
+                rank.cmp(other_rank)
+                    .then_with(|| found.identifier.cmp(&other.identifier))
+            });
+            Ok(Output::Found {
+                pattern,
+                matches: matches.into_iter().map(|(_, found)| found).collect(),
+            })
+        }
+        Command::List { tags, long } => {
+            let tags = parse_tags(&tags)?;
+            let (listing, entries) =
+                describe_listing(dirs, "", list_identifiers(totp_dir)?, &tags, long)?;
+            Ok(Output::List {
+                identifiers: listing.identifiers,
+                warnings: listing.warnings,
+                entries,
+                stores: dirs
+                    .stores
+                    .iter()
+                    .map(|(name, store_dir)| {
+                        let prefix = format!("{name}{STORE_SEPARATOR}");
+                        let ((listing, entries), error) = match list_identifiers(store_dir)
+                            .and_then(|listing| {
+                                describe_listing(dirs, &prefix, listing, &tags, long)
+                            }) {
+                            Ok(described) => (described, None),
+                            Err(error) => (Default::default(), Some(error_reason(&error))),
+                        };
+                        StoreEntries {
+                            name: name.clone(),
+                            store_dir: store_dir.clone(),
+                            identifiers: listing.identifiers,
+                            warnings: listing.warnings,
+                            entries,
+                            error,
+                        }
+                    })
+                    .collect(),
+            })
+        }
+        Command::Store { identifier } => {
+            let entry = new_entry(parse_secret(&interaction.read_key(&identifier)?)?, settings)?;
+            let (totp_dir, name) = dirs.locate(&identifier);
+            write_encrypted_key_to_file(gpg_home_dir, totp_dir, name, &entry.to_payload()?)?;
+            let warnings = reindex(gpg_home_dir, totp_dir, |index| index.set(name, &entry));
+            let path = key_file_path(totp_dir, name)?;
+            let message = format!("Store {identifier}");
+            let warnings = warnings
+                .into_iter()
+                .chain(commit_change(totp_dir, &[&path], &message))
+                .collect();
+            Ok(Output::Stored {
+                identifier,
+                stored: true,
+                warnings,
+            })
+        }
+        Command::Generate {
+            identifier,
+            bits,
+            algorithm,
+            digits,
+            period,
+            issuer,
+            account,
+            url,
+            tags,
+            qr,
+        } => {
+            let tags = parse_tags(&tags)?;
+            let (totp_dir, name) = dirs.locate(&identifier);
+            if key_file_exists(totp_dir, name)? {
+                return Err(Error::EntryExists(identifier));
             }
-            let gpg_id = args[2].as_str();
-            init(totp_dir, gpg_id)?;
-            Ok(format!("totp computer initialized with gpg id {}", gpg_id))
-        }
-        COMMAND_LIST | COMMAND_SHORT_LIST => Ok(print_list(&list_identifiers(totp_dir)?)),
-        COMMAND_SAVE | COMMAND_SHORT_SAVE => {
-            if args.len() < 3 {
-                return Err(ErrorMessage::MissingIdentifier(COMMAND_SAVE).into());
+            let mut entry = new_entry(encode(&generate_key(bits)?), settings)?;
+            entry.algorithm = algorithm;
+            entry.digits = digits.unwrap_or(entry.digits);
+            entry.period = period.unwrap_or(entry.period);
+            entry.metadata.issuer = issuer;
+            entry.metadata.account = account;
+            entry.metadata.url = url;
+            entry.metadata.tags = tags;
+            write_encrypted_key_to_file(gpg_home_dir, totp_dir, name, &entry.to_payload()?)?;
+            let warnings = reindex(gpg_home_dir, totp_dir, |index| index.set(name, &entry));
+            let path = key_file_path(totp_dir, name)?;
+            let message = format!("Generate {identifier}");
+            let warnings = warnings
+                .into_iter()
+                .chain(commit_change(totp_dir, &[&path], &message))
+                .collect();
+            let uri = otpauth_uri(name, &entry);
+            let qr_code = if qr { Some(qr_code(&uri)?) } else { None };
+            Ok(Output::Generated {
+                identifier,
+                uri,
+                qr_code,
+                warnings,
+            })
+        }
+        Command::Read { identifier } => {
+            let entry = read_entry(dirs, &identifier)?;
+            Ok(Output::Key {
+                identifier,
+                key: entry.secret,
+                algorithm: entry.algorithm,
+                digits: entry.digits,
+                period: entry.period,
+                metadata: entry.metadata,
+            })
+        }
+        Command::Delete { identifier } => {
+            let (totp_dir, name) = dirs.locate(&identifier);
+            delete_key_file(totp_dir, name).map_err(|error| match error {
+                Error::EntryNotFound(_) => Error::EntryNotFound(identifier.clone()),
+                error => error,
+            })?;
+            let warnings = reindex(gpg_home_dir, totp_dir, |index| {
+                index.entries.remove(name);
+            });
+            let path = key_file_path(totp_dir, name)?;
+            let message = format!("Delete {identifier}");
+            let warnings = warnings
+                .into_iter()
+                .chain(commit_change(totp_dir, &[&path], &message))
+                .collect();
+            Ok(Output::Deleted {
+                identifier,
+                deleted: true,
+                warnings,
+            })
+        }
+        Command::Edit { identifier } => {
+            let (totp_dir, name) = dirs.locate(&identifier);
+            let entry = read_entry(dirs, &identifier)?;
+            let edited_entry = Entry::parse(&interaction.edit(&entry.to_payload()?)?)?;
+            let edited = edited_entry != entry;
+            let mut warnings = vec![];
+            if edited {
+                let payload = edited_entry.to_payload()?;
+                replace_encrypted_key_in_file(gpg_home_dir, totp_dir, name, &payload)?;
+                warnings.extend(reindex(gpg_home_dir, totp_dir, |index| {
+                    index.set(name, &edited_entry)
+                }));
+                let path = key_file_path(totp_dir, name)?;
+                let message = format!("Edit {identifier}");
+                warnings.extend(commit_change(totp_dir, &[&path], &message));
             }
-            let identifier = args[2].as_str();
-            let key_base32 = read_key_input(identifier)?;
-            write_encrypted_key_to_file(gpg_home_dir, totp_dir, identifier, &key_base32)?;
-            Ok(format!("Key for {identifier} stored."))
-        }
-        COMMAND_LOAD | COMMAND_SHORT_LOAD => {
-            if args.len() < 3 {
-                return Err(ErrorMessage::MissingIdentifier(COMMAND_LOAD).into());
+            Ok(Output::Edited {
+                identifier,
+                edited,
+                warnings,
+            })
+        }
+        Command::Tag { action } => {
+            let (identifier, tags, add) = match action {
+                TagAction::Add { identifier, tags } => (identifier, tags, true),
+                TagAction::Remove { identifier, tags } => (identifier, tags, false),
+            };
+            let changed_tags = parse_tags(&tags)?;
+            let (totp_dir, name) = dirs.locate(&identifier);
+            let mut entry = read_entry(dirs, &identifier)?;
+            let previous_tags = entry.metadata.tags.clone();
+            if add {
+                entry.metadata.tags.extend(changed_tags);
+                entry.metadata.tags.sort();
+                entry.metadata.tags.dedup();
+            } else {
+                entry
+                    .metadata
+                    .tags
+                    .retain(|tag| !changed_tags.contains(tag));
+            }
+            let mut warnings = vec![];
+            if entry.metadata.tags != previous_tags {
+                let payload = entry.to_payload()?;
+                replace_encrypted_key_in_file(gpg_home_dir, totp_dir, name, &payload)?;
+                warnings.extend(reindex(gpg_home_dir, totp_dir, |index| {
+                    index.set(name, &entry)
+                }));
+                let path = key_file_path(totp_dir, name)?;
+                let message = format!("Tag {identifier}");
+                warnings.extend(commit_change(totp_dir, &[&path], &message));
             }
-            let identifier = args[2].as_str();
-            match read_decrypted_key_from_file(gpg_home_dir, totp_dir, identifier)? {
-                None => Ok(format!("Identifier {identifier} not found.")),
-                Some(key) => Ok(format!("Key for {identifier}: {key}")),
+            Ok(Output::Tagged {
+                identifier,
+                tags: entry.metadata.tags,
+                warnings,
+            })
+        }
+        Command::Index => {
+            let mut indexed = 0;
+            let mut warnings = vec![];
+            let additional_stores = dirs.stores.values().map(|store_dir| (store_dir, true));
+            for (store_dir, is_additional) in
+                once((&dirs.store_dir, false)).chain(additional_stores)
+            {
+                match rebuild_index(gpg_home_dir, store_dir) {
+                    Ok((index, store_warnings)) => {
+                        indexed += index.entries.len();
+                        warnings.extend(store_warnings);
+                    }
+                    Err(error) if is_additional => warnings.push(Warning {
+                        path: Some(store_dir.clone()),
+                        message: format!("not indexed - {}", error_reason(&error)),
+                    }),
+                    Err(error) => return Err(error),
+                }
             }
+            Ok(Output::Indexed { indexed, warnings })
         }
-        COMMAND_DELETE => {
-            if args.len() < 3 {
-                return Err(ErrorMessage::MissingIdentifier(COMMAND_DELETE).into());
+        Command::Check => {
+            let time = current_time()?;
+            let mut checked = 0;
+            let mut problems = vec![];
+            for store_dir in once(&dirs.store_dir).chain(dirs.stores.values()) {
+                match check::check_store(gpg_home_dir, store_dir, time) {
+                    Ok((store_checked, store_problems)) => {
+                        checked += store_checked;
+                        problems.extend(store_problems);
+                    }
+                    // the other stores are checked all the same
+                    Err(error) => problems.push(Warning {
+                        path: Some(store_dir.clone()),
+                        message: format!("not checked - {}", error_reason(&error)),
+                    }),
+                }
+            }
+            match problems.is_empty() {
+                true => Ok(Output::Checked { checked }),
+                false => Err(Error::StoreProblems { checked, problems }),
             }
-            let identifier = args[2].as_str();
-            delete_key_file(totp_dir, identifier)?;
-            Ok(format!("Key for {identifier} deleted."))
         }
-        COMMAND_COMPUTE | COMMAND_SHORT_COMPUTE => {
-            if args.len() < 3 {
-                return Err(ErrorMessage::MissingIdentifier(COMMAND_COMPUTE).into());
+        Command::Move {
+            source,
+            target,
+            force,
+        } => {
+            let warnings = transfer_key(dirs, &source, &target, force, true)?;
+            Ok(Output::Moved {
+                source,
+                target,
+                moved: true,
+                warnings,
+            })
+        }
+        Command::Copy {
+            source,
+            target,
+            force,
+        } => {
+            let warnings = transfer_key(dirs, &source, &target, force, false)?;
+            Ok(Output::Copied {
+                source,
+                target,
+                copied: true,
+                warnings,
+            })
+        }
+        Command::Compute {
+            identifier,
+            all: _,
+            tags,
+            next,
+            at,
+            offset,
+        } => {
+            let time = match at {
+                Some(time) => time,
+                None => current_time()?,
+            };
+            let time = apply_offset(time, offset)?;
+            let Some(identifier) = identifier else {
+                return compute_all(dirs, time, next, &parse_tags(&tags)?);
+            };
+            let identifier = resolve_identifier(dirs, &identifier, interaction)?;
+            let (totp, valid_for, next_totp) = compute_codes(
+                dirs,
+                &identifier,
+                time,
+                next,
+                settings.record_last_used.unwrap_or_default(),
+            )?;
+            Ok(Output::Totp {
+                identifier,
+                totp,
+                time,
+                valid_for,
+                next_totp,
+                is_current: at.is_none() && offset == 0,
+            })
+        }
+        Command::Verify {
+            identifier,
+            code,
+            window,
+        } => {
+            let code = code.trim().to_string();
+            let totp = read_entry(dirs, &identifier)?.totp()?;
+            match totp.verify(&code, current_time()?, window)? {
+                None => Err(Error::InvalidCode {
+                    identifier,
+                    code,
+                    window,
+                }),
+                Some(offset) => Ok(Output::Verified {
+                    identifier,
+                    code,
+                    valid: true,
+                    offset,
+                }),
             }
-            let identifier = args[2].as_str();
-            let maybe_key_base32 = read_decrypted_key_from_file(gpg_home_dir, totp_dir, identifier)
-                .map_err(|error| format!("Error reading file - {error}"))?;
-            match maybe_key_base32 {
-                None => Err(format!("Error: no entry found for {identifier}")),
-                Some(key_base32) => {
-                    let key = decode(&key_base32)?;
-                    let time = std::time::SystemTime::UNIX_EPOCH
-                        .elapsed()
-                        .map_err(|error| {
-                            format!("Error: could not determine current system time - {error}",)
-                        })?
-                        .as_secs();
-                    let time_step_interval = 30;
-                    let time_step = time / time_step_interval;
-                    let totp = compute(&key, time_step)?;
-                    Ok(format!("Current TOTP for {identifier} is {totp}"))
+        }
+        Command::Menu { type_code, picker } => {
+            let tools = Tools::new(
+                picker.or_else(|| settings.picker.clone()),
+                settings.clipboard.clone(),
+                settings.typer.clone(),
+                settings.paste.clone(),
+            );
+            let (identifiers, _) = list_all_identifiers(dirs)?;
+            let identifiers = identifiers.into_iter().flatten().collect::<Vec<_>>();
+            let Some(identifier) = pick(&tools.picker, &identifiers)? else {
+                return Ok(Output::Silent);
+            };
+            let (totp, _, _) = compute_codes(
+                dirs,
+                &identifier,
+                current_time()?,
+                false,
+                settings.record_last_used.unwrap_or_default(),
+            )?;
+            if type_code {
+                send(&tools.typer, &totp)?;
+            } else {
+                send(&tools.clipboard, &totp)?;
+                if let Some(seconds) = settings.clipboard_timeout {
+                    clear_later(&tools, &totp, seconds)?;
                 }
             }
+            Ok(Output::Picked {
+                identifier,
+                typed: type_code,
+            })
         }
-        COMMAND_HELP => Ok(get_help_text()),
-        _ => Err(format!(
-            "Error: unknown command \"{command}\"\n\n{}",
-            get_help_text()
+        Command::Tui => {
+            tui::run(dirs, settings)?;
+            Ok(Output::Silent)
+        }
+        #[cfg(unix)]
+        Command::Agent { ttl } => {
+            let Some(socket) = &dirs.agent_socket else {
+                return Err(Error::InvalidArgument(
+                    "could not determine agent socket - set $TOTPC_AGENT_SOCKET".to_string(),
+                ));
+            };
+            let ttl = ttl
+                .or(settings.agent_ttl)
+                .unwrap_or(agent::DEFAULT_AGENT_TTL);
+            match agent::serve(socket, std::time::Duration::from_secs(ttl))? {}
+        }
+        #[cfg(not(unix))]
+        Command::Agent { .. } => Err(Error::InvalidArgument(
+            "the agent is only supported on Unix".to_string(),
         )),
+        Command::NativeHost { .. } => {
+            native_host::serve(dirs, &mut stdin().lock(), &mut stdout().lock())?;
+            Ok(Output::Silent)
+        }
+        Command::Completions { shell } => Ok(Output::Script {
+            script: script(shell),
+        }),
+        Command::Complete { words } => Ok(Output::Candidates {
+            candidates: complete(&words, dirs),
+        }),
     }
 }
 
-fn read_key_input(identifier: &str) -> Result<String, String> {
-    println!("Enter key for {identifier}: ");
-    let mut key_base32 = String::new();
-    stdin()
-        .read_line(&mut key_base32)
-        .map_err(|error| format!("Error entering key: {}", error))?;
-    if key_base32.is_empty() {
-        return Err(ErrorMessage::EmptyKey.into());
+/// Decrypt and parse the entry of the given identifier, which may be
+/// qualified with the name of an additional store.
+fn read_entry(dirs: &Dirs, identifier: &str) -> Result<Entry, Error> {
+    let (totp_dir, name) = dirs.locate(identifier);
+    Store::open(totp_dir, &dirs.gnupg_home)?
+        .get(name)
+        .map_err(|error| match error {
+            Error::EntryNotFound(_) => Error::EntryNotFound(identifier.to_string()),
+            error => error,
+        })
+}
+
+/// Identifier of the entry that the given identifier refers to: the identifier
+/// itself if it exists, else the only identifier that matches it best, e. g.
+/// by prefix. The user chooses if several identifiers match equally well.
+fn resolve_identifier(
+    dirs: &Dirs,
+    identifier: &str,
+    interaction: &mut dyn Interaction,
+) -> Result<String, Error> {
+    let (totp_dir, name) = dirs.locate(identifier);
+    if key_file_exists(totp_dir, name)? {
+        return Ok(identifier.to_string());
+    }
+    let (identifiers, _) = list_all_identifiers(dirs)?;
+    let mut matches = identifiers
+        .into_iter()
+        .flatten()
+        .filter_map(|candidate| {
+            rank_identifier(identifier, &candidate).map(|rank| (rank, candidate))
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    let Some((best_rank, _)) = matches.first().cloned() else {
+        return Err(Error::EntryNotFound(identifier.to_string()));
+    };
+    let best = matches
+        .into_iter()
+        .take_while(|(rank, _)| rank.is_same_kind(&best_rank))
+        .map(|(_, candidate)| candidate)
+        .collect::<Vec<_>>();
+    match best.as_slice() {
+        [candidate] => Ok(candidate.clone()),
+        _ => interaction.choose(identifier, &best),
     }
-    key_base32 = key_base32
-        .trim()
-        .replace(' ', "")
-        .to_string()
-        .to_uppercase();
-    // verify valid Base32 encoding of key
-    base32::decode(&key_base32)?;
-    Ok(key_base32)
 }
 
-fn print_list(identifier_list: &[String]) -> String {
-    let mut printed_list = String::from(IDENTIFIER_LIST_HEADER);
-    if let Some((last_identifier, identifiers)) = identifier_list.split_last() {
-        for identifier in identifiers {
-            printed_list.push_str(format!("{IDENTIFIER_LIST_ITEM_PREFIX} {identifier}\n").as_str())
+/// Password of the given identifier at the given time, the seconds it remains
+/// valid and the password of the next time step if requested. Asks the agent
+/// if it is running, so that the key is not decrypted again. Otherwise
+/// records the time of use in the index of the store if `record_use` is set.
+fn compute_codes(
+    dirs: &Dirs,
+    identifier: &str,
+    time: u64,
+    next: bool,
+    record_use: bool,
+) -> Result<(String, u64, Option<String>), Error> {
+    #[cfg(unix)]
+    if let Some(socket) = &dirs.agent_socket {
+        let (totp_dir, name) = dirs.locate(identifier);
+        if let Some(result) =
+            agent::request_codes(socket, totp_dir, &dirs.gnupg_home, name, time, next)
+        {
+            return result.map_err(|error| match error {
+                Error::Agent { exit_code: 4, .. } => Error::EntryNotFound(identifier.to_string()),
+                error => error,
+            });
         }
-        printed_list
-            .push_str(format!("{IDENTIFIER_LIST_LAST_ITEM_PREFIX} {}", last_identifier).as_str());
-    } else {
-        printed_list.push_str("--- empty ---")
     }
-    printed_list
+    let codes = entry_codes(&read_entry(dirs, identifier)?, time, next)?;
+    if record_use {
+        // The time of use is informational, so failing to record it is fine.
+        let (totp_dir, name) = dirs.locate(identifier);
+        let now = current_time().map(format_rfc3339).ok();
+        let _ = update_index(&dirs.gnupg_home, totp_dir, |index| {
+            if let Some(indexed) = index.entries.get_mut(name) {
+                indexed.last_used = now;
+            }
+        });
+    }
+    Ok(codes)
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        print_list, IDENTIFIER_LIST_HEADER, IDENTIFIER_LIST_ITEM_PREFIX,
-        IDENTIFIER_LIST_LAST_ITEM_PREFIX,
+/// Password of the given entry at the given time, the seconds it remains
+/// valid and the password of the next time step if requested.
+fn entry_codes(
+    entry: &Entry,
+    time: u64,
+    next: bool,
+) -> Result<(String, u64, Option<String>), Error> {
+    let totp = entry.totp()?;
+    let next_totp = if next {
+        Some(totp.generate_next_at(time)?)
+    } else {
+        None
     };
+    Ok((totp.generate_at(time)?, totp.valid_for(time), next_totp))
+}
 
-    #[test]
-    fn print_identifier_list() {
-        let identifiers = vec![
-            String::from("identifier_1"),
-            String::from("identifier_2"),
-            String::from("identifier_3"),
-        ];
-        let mut expected_printed_list = IDENTIFIER_LIST_HEADER.to_string();
-        identifiers[0..identifiers.len() - 1]
-            .iter()
-            .for_each(|identifier| {
-                expected_printed_list
-                    .push_str(format!("{IDENTIFIER_LIST_ITEM_PREFIX} {identifier}\n").as_str())
-            });
-        expected_printed_list.push_str(
-            format!(
-                "{IDENTIFIER_LIST_LAST_ITEM_PREFIX} {}",
-                identifiers.last().unwrap()
-            )
-            .as_str(),
+/// Tags entered on the command line, checked and without duplicates.
+fn parse_tags(tags: &[String]) -> Result<Vec<String>, Error> {
+    let mut tags = tags
+        .iter()
+        .map(|tag| parse_tag(tag))
+        .collect::<Result<Vec<_>, _>>()?;
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+/// Whether the entry tags include all of the given tags.
+fn has_tags(entry_tags: &[String], tags: &[String]) -> bool {
+    tags.iter().all(|tag| entry_tags.contains(tag))
+}
+
+/// Index entries of the given identifiers, which may be qualified with the
+/// name of an additional store, from the indexes of their stores. Entries
+/// missing from an index, or of stores without one or whose index cannot be
+/// read, are decrypted instead, unless decrypting the index was cancelled.
+fn index_entries(
+    dirs: &Dirs,
+    identifiers: &[String],
+) -> Result<Vec<Result<IndexEntry, Error>>, Error> {
+    let mut indexes = HashMap::<PathBuf, _>::new();
+    let mut entries = vec![];
+    for identifier in identifiers {
+        let (totp_dir, name) = dirs.locate(identifier);
+        let index = match indexes.get(totp_dir) {
+            Some(index) => index,
+            None => {
+                let index = match read_index(&dirs.gnupg_home, totp_dir, true) {
+                    Err(Error::DecryptionCancelled) => return Err(Error::DecryptionCancelled),
+                    result => result.ok().flatten(),
+                };
+                indexes.entry(totp_dir.to_path_buf()).or_insert(index)
+            }
+        };
+        entries.push(
+            match index.as_ref().and_then(|index| index.entries.get(name)) {
+                Some(indexed) => Ok(indexed.clone()),
+                None => read_entry(dirs, identifier).map(|entry| IndexEntry::new(&entry)),
+            },
         );
+    }
+    Ok(entries)
+}
+
+/// Keep the identifiers of the listing, which are qualified with the given
+/// store prefix, whose entries have all of the given tags, with their index
+/// entries if `long` is set. Entries whose metadata cannot be read are
+/// reported as warnings, and left out if tags are given.
+fn describe_listing(
+    dirs: &Dirs,
+    prefix: &str,
+    listing: Listing,
+    tags: &[String],
+    long: bool,
+) -> Result<(Listing, Vec<ListedEntry>), Error> {
+    if tags.is_empty() && !long {
+        return Ok((listing, vec![]));
+    }
+    let qualified = listing
+        .identifiers
+        .iter()
+        .map(|identifier| format!("{prefix}{identifier}"))
+        .collect::<Vec<_>>();
+    let index_entries = index_entries(dirs, &qualified)?;
+    let mut described = Listing {
+        identifiers: vec![],
+        warnings: listing.warnings,
+    };
+    let mut entries = vec![];
+    for ((identifier, qualified), indexed) in listing
+        .identifiers
+        .into_iter()
+        .zip(qualified)
+        .zip(index_entries)
+    {
+        match indexed {
+            Ok(indexed) if !has_tags(&indexed.tags, tags) => {}
+            Ok(indexed) => {
+                if long {
+                    entries.push(ListedEntry {
+                        identifier: identifier.clone(),
+                        metadata: indexed,
+                    });
+                }
+                described.identifiers.push(identifier);
+            }
+            Err(error) => {
+                let (totp_dir, name) = dirs.locate(&qualified);
+                described.warnings.push(Warning {
+                    path: key_file_path(totp_dir, name).ok(),
+                    message: format!("metadata unknown - {}", error_reason(&error)),
+                });
+                if tags.is_empty() {
+                    described.identifiers.push(identifier);
+                }
+            }
+        }
+    }
+    Ok((described, entries))
+}
+
+/// Entry with the given Base32 encoded secret, the digits and period of the
+/// given settings and the current time as creation date.
+fn new_entry(secret: String, settings: &Settings) -> Result<Entry, Error> {
+    let mut entry = Entry::new(secret);
+    entry.digits = settings.digits.unwrap_or(entry.digits);
+    entry.period = settings.period.unwrap_or(entry.period);
+    entry.metadata.created = Some(format_rfc3339(current_time()?));
+    Ok(entry)
+}
 
-        let printed_list = print_list(&identifiers);
+/// Move or copy the key of `source` to `target`, which may be in different
+/// stores, and commit the changes in the git repositories of the stores.
+/// Changes that cannot be committed are reported as warnings, as the keys
+/// have been moved or copied already.
+fn transfer_key(
+    dirs: &Dirs,
+    source: &str,
+    target: &str,
+    force: bool,
+    remove_source: bool,
+) -> Result<Vec<Warning>, Error> {
+    let gpg_home_dir = dirs.gnupg_home.as_path();
+    let (source_dir, source_name) = dirs.locate(source);
+    let (target_dir, target_name) = dirs.locate(target);
+    let (result, message) = if remove_source {
+        (
+            move_key_file(
+                gpg_home_dir,
+                source_dir,
+                source_name,
+                target_dir,
+                target_name,
+                force,
+            ),
+            format!("Move {source} to {target}"),
+        )
+    } else {
+        (
+            copy_key_file(
+                gpg_home_dir,
+                source_dir,
+                source_name,
+                target_dir,
+                target_name,
+                force,
+            ),
+            format!("Copy {source} to {target}"),
+        )
+    };
+    result.map_err(|error| match error {
+        Error::EntryNotFound(_) => Error::EntryNotFound(source.to_string()),
+        Error::EntryExists(_) => Error::EntryExists(target.to_string()),
+        error => error,
+    })?;
+
+    let indexed = read_index(gpg_home_dir, source_dir, true)
+        .ok()
+        .flatten()
+        .and_then(|mut index| index.entries.remove(source_name));
+    let mut warnings = vec![];
+    warnings.extend(reindex(gpg_home_dir, target_dir, |index| match indexed {
+        Some(indexed) => {
+            index.entries.insert(target_name.to_string(), indexed);
+        }
+        // decrypted instead until the index is rebuilt
+        None => {
+            index.entries.remove(target_name);
+        }
+    }));
+    if remove_source {
+        warnings.extend(reindex(gpg_home_dir, source_dir, |index| {
+            index.entries.remove(source_name);
+        }));
+    }
+
+    let source_path = key_file_path(source_dir, source_name)?;
+    let target_path = key_file_path(target_dir, target_name)?;
+    let commits = if !remove_source {
+        vec![(target_dir, vec![target_path.as_path()])]
+    } else if source_dir == target_dir {
+        vec![(target_dir, vec![source_path.as_path(), &target_path])]
+    } else {
+        vec![
+            (source_dir, vec![source_path.as_path()]),
+            (target_dir, vec![&target_path]),
+        ]
+    };
+    warnings.extend(
+        commits
+            .into_iter()
+            .filter_map(|(store_dir, paths)| commit_change(store_dir, &paths, &message)),
+    );
+    Ok(warnings)
+}
+
+/// Commit the changes of the given files in the git repository of the store
+/// directory, reporting a failure as warning, as the change itself is done.
+fn commit_change(store_dir: &Path, paths: &[&Path], message: &str) -> Option<Warning> {
+    let error = commit(store_dir, paths, message).err()?;
+    Some(Warning {
+        path: Some(store_dir.to_path_buf()),
+        message: format!("not committed - {}", error_reason(&error)),
+    })
+}
+
+/// Compute the TOTPs of all identifiers in all stores at the given time, or
+/// of the ones whose entries have all of the given tags. Entries and
+/// additional stores that fail to decrypt, compute or list are reported
+/// without aborting the others.
+fn compute_all(dirs: &Dirs, time: u64, next: bool, tags: &[String]) -> Result<Output, Error> {
+    let (identifiers, warnings) = list_all_identifiers(dirs)?;
+    let entries = identifiers
+        .into_iter()
+        .filter_map(|identifier| {
+            let (identifier, result) = match identifier {
+                Err((identifier, error)) => (identifier, Err(error)),
+                Ok(identifier) if tags.is_empty() => {
+                    let result = compute_codes(dirs, &identifier, time, next, false);
+                    (identifier, result)
+                }
+                Ok(identifier) => match read_entry(dirs, &identifier) {
+                    Ok(entry) if !has_tags(&entry.metadata.tags, tags) => return None,
+                    result => {
+                        let result = result.and_then(|entry| entry_codes(&entry, time, next));
+                        (identifier, result)
+                    }
+                },
+            };
+            Some(match result {
+                Ok((totp, valid_for, next_totp)) => TotpEntry {
+                    identifier,
+                    totp: Some(totp),
+                    valid_for: Some(valid_for),
+                    next_totp,
+                    error: None,
+                },
+                Err(error) => TotpEntry {
+                    identifier,
+                    totp: None,
+                    valid_for: None,
+                    next_totp: None,
+                    error: Some(error_reason(&error)),
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    // entries may have periods other than the default one
+    let valid_for = entries
+        .iter()
+        .filter_map(|entry| entry.valid_for)
+        .min()
+        .unwrap_or_else(|| seconds_remaining(time, TIME_STEP_INTERVAL));
+    Ok(Output::TotpTable {
+        time,
+        valid_for,
+        entries,
+        warnings,
+    })
+}
 
-        assert_eq!(printed_list, expected_printed_list);
+/// Identifier, or the prefix of an additional store that failed to list with
+/// the error.
+type ListedIdentifier = Result<String, (String, Error)>;
+
+/// Identifiers of the default store and qualified identifiers of the
+/// additional stores, with warnings about skipped files. Additional stores
+/// that cannot be listed are reported as error with the store prefix.
+fn list_all_identifiers(dirs: &Dirs) -> Result<(Vec<ListedIdentifier>, Vec<Warning>), Error> {
+    let listing = list_identifiers(&dirs.store_dir)?;
+    let mut warnings = listing.warnings;
+    let mut identifiers = listing.identifiers.into_iter().map(Ok).collect::<Vec<_>>();
+    for (name, store_dir) in &dirs.stores {
+        match list_identifiers(store_dir) {
+            Err(error) => identifiers.push(Err((format!("{name}{STORE_SEPARATOR}"), error))),
+            Ok(listing) => {
+                warnings.extend(listing.warnings);
+                identifiers.extend(
+                    listing
+                        .identifiers
+                        .into_iter()
+                        .map(|identifier| Ok(format!("{name}{STORE_SEPARATOR}{identifier}"))),
+                );
+            }
+        }
     }
+    Ok((identifiers, warnings))
+}
+
+/// Last non-empty line of the given error, e. g. the reason reported by gpg.
+fn error_reason(error: &Error) -> String {
+    error
+        .to_string()
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Current Unix time in seconds.
+fn current_time() -> Result<u64, Error> {
+    Ok(std::time::SystemTime::UNIX_EPOCH
+        .elapsed()
+        .map_err(|error| {
+            Error::Other(format!("could not determine current system time - {error}"))
+        })?
+        .as_secs())
 }
@@ -1,18 +1,101 @@
-use std::{env, path::Path, process};
+use std::{
+    env,
+    io::{stderr, stdin, IsTerminal, Write},
+    process,
+};
 
-use totpc::{run, TOTP_DIR_NAME};
+use clap::Parser;
+use totpc::{
+    edit_in_editor, editor, render_error, run, Cli, Command, Config, Dirs, Error, Format,
+    Interaction, ENV_PROFILE,
+};
+
+/// Interaction through the terminal the command runs in.
+struct Terminal;
+
+impl Interaction for Terminal {
+    fn read_key(&mut self, identifier: &str) -> Result<String, Error> {
+        eprintln!("Enter key for {identifier}: ");
+        stderr()
+            .flush()
+            .map_err(|error| Error::io("error prompting for key", error))?;
+        let mut key_input = String::new();
+        stdin()
+            .read_line(&mut key_input)
+            .map_err(|error| Error::io("error entering key", error))?;
+        Ok(key_input)
+    }
+
+    fn edit(&mut self, payload: &str) -> Result<String, Error> {
+        edit_in_editor(&editor(), payload)
+    }
+
+    fn choose(&mut self, pattern: &str, identifiers: &[String]) -> Result<String, Error> {
+        let ambiguous = || {
+            Error::InvalidArgument(format!(
+                "{pattern} matches several identifiers: {}",
+                identifiers.join(", ")
+            ))
+        };
+        if !stdin().is_terminal() {
+            return Err(ambiguous());
+        }
+        eprintln!("{pattern} matches several identifiers:");
+        for (number, identifier) in identifiers.iter().enumerate() {
+            eprintln!("  {}) {identifier}", number + 1);
+        }
+        eprint!("Choose 1-{}: ", identifiers.len());
+        let mut choice = String::new();
+        stdin()
+            .read_line(&mut choice)
+            .map_err(|error| Error::io("error entering choice", error))?;
+        choice
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| identifiers.get(number.checked_sub(1)?))
+            .cloned()
+            .ok_or_else(ambiguous)
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let base_dir = std::env::var("HOME").unwrap_or_else(|_| "./".to_string());
-    let base_dir_path = Path::new(&base_dir);
-    let gpg_home_dir = base_dir_path.join(".gnupg");
-    let totp_dir = base_dir_path.join(TOTP_DIR_NAME);
-    match run(&gpg_home_dir, &totp_dir, args) {
+    let cli = Cli::parse();
+    let env_var = |name: &str| env::var_os(name);
+    let profile = cli.profile.or_else(|| {
+        env::var(ENV_PROFILE)
+            .ok()
+            .filter(|profile| !profile.is_empty())
+    });
+    let settings = match Config::load(cli.config, env_var)
+        .and_then(|config| config.settings(profile.as_deref()))
+    {
+        Err(error) => exit_with_error(&error, cli.format.unwrap_or_default()),
+        Ok(settings) => settings,
+    };
+    let format = cli.format.or(settings.format).unwrap_or_default();
+    let dirs = match Dirs::resolve(cli.store_dir, cli.gnupg_home, &settings, env_var) {
+        Err(error) => exit_with_error(&error, format),
+        Ok(dirs) => dirs,
+    };
+    let command = cli.command.unwrap_or(Command::List {
+        tags: vec![],
+        long: false,
+    });
+    match run(&dirs, &settings, format, command, &mut Terminal) {
         Err(error) => {
-            eprintln!("{}", error);
-            process::exit(1)
+            // the codes of the other entries are output nonetheless
+            if let Error::ComputeFailed { output, .. } = &error {
+                println!("{output}");
+            }
+            exit_with_error(&error, format)
         }
+        Ok(output) if output.is_empty() => {}
         Ok(output) => println!("{}", output),
     }
 }
+
+fn exit_with_error(error: &Error, format: Format) -> ! {
+    eprintln!("{}", render_error(error, format));
+    process::exit(error.exit_code())
+}
@@ -0,0 +1,204 @@
+use std::{
+    env,
+    io::{ErrorKind, Write},
+    process::{Command, ExitStatus, Output, Stdio},
+};
+
+use crate::error::Error;
+
+const ENV_WAYLAND_DISPLAY: &str = "WAYLAND_DISPLAY";
+const ENV_DISPLAY: &str = "DISPLAY";
+
+/// Picker, clipboard, typing and paste commands of the menu, each run through
+/// the shell with the text on stdin.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tools {
+    /// Command that reads candidates as lines and outputs the chosen one.
+    pub picker: String,
+    /// Command that copies its input to the clipboard.
+    pub clipboard: String,
+    /// Command that types its input into the focused window.
+    pub typer: String,
+    /// Command that outputs the content of the clipboard.
+    pub paste: String,
+}
+
+impl Tools {
+    /// Tools of the given commands, defaulting to the ones of the running
+    /// display server: `wofi`, `wl-copy`, `wtype` and `wl-paste` on Wayland,
+    /// `dmenu`, `xclip` and `xdotool` on X11 and `fzf` without either.
+    pub fn new(
+        picker: Option<String>,
+        clipboard: Option<String>,
+        typer: Option<String>,
+        paste: Option<String>,
+    ) -> Self {
+        let is_set = |name| env::var_os(name).is_some_and(|value| !value.is_empty());
+        let (default_picker, default_clipboard, default_typer, default_paste) =
+            if is_set(ENV_WAYLAND_DISPLAY) {
+                (
+                    "wofi --dmenu",
+                    "wl-copy",
+                    "wtype -",
+                    "wl-paste --no-newline",
+                )
+            } else if is_set(ENV_DISPLAY) {
+                (
+                    "dmenu",
+                    "xclip -selection clipboard",
+                    "xdotool type --clearmodifiers --file -",
+                    "xclip -selection clipboard -o",
+                )
+            } else {
+                (
+                    "fzf",
+                    "xclip -selection clipboard",
+                    "xdotool type --file -",
+                    "xclip -selection clipboard -o",
+                )
+            };
+        Self {
+            picker: picker.unwrap_or_else(|| default_picker.to_string()),
+            clipboard: clipboard.unwrap_or_else(|| default_clipboard.to_string()),
+            typer: typer.unwrap_or_else(|| default_typer.to_string()),
+            paste: paste.unwrap_or_else(|| default_paste.to_string()),
+        }
+    }
+}
+
+/// Let the user choose one of the candidates with the picker command. Returns
+/// `None` if the picker is cancelled or outputs nothing.
+pub fn pick(picker: &str, candidates: &[String]) -> Result<Option<String>, Error> {
+    let input = candidates
+        .iter()
+        .fold(String::new(), |mut input, candidate| {
+            input.push_str(candidate);
+            input.push('\n');
+            input
+        });
+    let output = run(picker, &input, Stdio::piped())?;
+    // Pickers that are cancelled or find no match exit with status 1, or 130
+    // for fzf.
+    match output.status.code() {
+        Some(0) => {}
+        Some(1 | 130) if output.stdout.is_empty() => return Ok(None),
+        _ => return Err(failed(picker, output.status)),
+    }
+    let choice = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(choice).filter(|choice| !choice.is_empty()))
+}
+
+/// Pass the text to the clipboard or typing command.
+pub fn send(command: &str, text: &str) -> Result<(), Error> {
+    let output = run(command, text, Stdio::null())?;
+    if !output.status.success() {
+        return Err(failed(command, output.status));
+    }
+    Ok(())
+}
+
+/// Clear the clipboard with the clipboard command after the given number of
+/// seconds, in a shell that outlives the process, unless it no longer holds
+/// the code, like `pass -c`.
+pub fn clear_later(tools: &Tools, code: &str, seconds: u64) -> Result<(), Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(clear_script(tools, seconds))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| Error::io("error clearing clipboard", err))?;
+    // the code is passed on stdin, so that it does not show in the process
+    // list
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(code.as_bytes())
+            .map_err(|err| Error::io("error clearing clipboard", err))?;
+    }
+    Ok(())
+}
+
+/// Shell script that reads the code on stdin and clears the clipboard after
+/// the given number of seconds if it still holds the code.
+fn clear_script(tools: &Tools, seconds: u64) -> String {
+    format!(
+        r#"code=$(cat); sleep {seconds}; if [ "$({})" = "$code" ]; then printf '' | {}; fi"#,
+        tools.paste, tools.clipboard
+    )
+}
+
+/// Run the command through the shell, so that it may contain arguments, with
+/// the input on stdin.
+fn run(command: &str, input: &str, stdout: Stdio) -> Result<Output, Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .spawn()
+        .map_err(|err| Error::io(format!("error running {command}"), err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(input.as_bytes()) {
+            // the command may exit without reading all input
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => {}
+            result => {
+                result.map_err(|err| Error::io(format!("error writing to {command}"), err))?
+            }
+        }
+    }
+    child
+        .wait_with_output()
+        .map_err(|err| Error::io(format!("error running {command}"), err))
+}
+
+fn failed(command: &str, status: ExitStatus) -> Error {
+    Error::Other(format!("command {command} exited with {status}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::read_to_string, process::Stdio};
+
+    use tempfile::TempDir;
+
+    use crate::menu::{clear_script, pick, run, send, Tools};
+
+    #[test]
+    fn pick_returns_choice() {
+        let candidates = ["foo".to_string(), "team:bar".to_string()];
+
+        assert_eq!(
+            pick("grep bar", &candidates).unwrap(),
+            Some("team:bar".to_string())
+        );
+        assert_eq!(pick("grep none", &candidates).unwrap(), None);
+        assert!(pick("exit 2", &candidates).is_err());
+    }
+
+    #[test]
+    fn send_fails_on_failing_command() {
+        assert!(send("cat > /dev/null", "123456").is_ok());
+        assert!(send("exit 1", "123456").is_err());
+    }
+
+    #[test]
+    fn clipboard_is_cleared_only_if_it_holds_code() {
+        let dir = TempDir::new().unwrap();
+        let clipboard = dir.path().join("clipboard");
+        let tools = Tools::new(
+            None,
+            Some(format!("cat > {}", clipboard.display())),
+            None,
+            Some(format!("cat {}", clipboard.display())),
+        );
+        let script = clear_script(&tools, 0);
+
+        send(&tools.clipboard, "654321").unwrap();
+        run(&script, "123456", Stdio::null()).unwrap();
+        assert_eq!(read_to_string(&clipboard).unwrap(), "654321");
+
+        run(&script, "654321", Stdio::null()).unwrap();
+        assert_eq!(read_to_string(&clipboard).unwrap(), "");
+    }
+}
@@ -0,0 +1,251 @@
+//! Host for browser extensions, speaking the WebExtension native messaging
+//! protocol on stdin and stdout: every message is a JSON object, preceded by
+//! its length in bytes as 32 bit unsigned integer in native byte order.
+//!
+//! Requests and their responses:
+//!
+//! - `{"action": "ping"}`: `{"version": string}`
+//! - `{"action": "code", "origin": string}`: `{"matches": [{"identifier":
+//!   string, "issuer"?: string, "account"?: string, "totp": string,
+//!   "valid_for": number}]}` with the current passwords of the entries whose
+//!   `url` is on exactly the host of the origin, which must be https, e. g.
+//!   `https://github.com`
+//! - errors: `{"error": string, "exit_code": number}`
+
+use std::io::{ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    current_time, dirs::Dirs, entry::Metadata, error::Error, list_all_identifiers, read_entry,
+};
+
+/// Maximum length of a request, far above any valid one.
+const MAX_REQUEST_LENGTH: u32 = 64 * 1024;
+
+/// Request of the browser extension.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Request {
+    /// Check that the host is installed.
+    Ping,
+    /// Compute the passwords of the entries matching the origin of a page.
+    Code { origin: String },
+}
+
+/// Response to the browser extension.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Response {
+    Pong { version: &'static str },
+    Matches { matches: Vec<Match> },
+    Error { error: String, exit_code: i32 },
+}
+
+/// Current password of an entry matching the origin.
+#[derive(Debug, PartialEq, Serialize)]
+struct Match {
+    identifier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<String>,
+    totp: String,
+    valid_for: u64,
+}
+
+/// Answer requests read from `input` on `output` until `input` is closed.
+pub fn serve(dirs: &Dirs, input: &mut impl Read, output: &mut impl Write) -> Result<(), Error> {
+    while let Some(message) = read_message(input)? {
+        let response = match serde_json::from_slice(&message)
+            .map_err(|error| Error::InvalidArgument(format!("invalid request - {error}")))
+            .and_then(|request| answer(dirs, request))
+        {
+            Ok(response) => response,
+            Err(error) => Response::Error {
+                error: error.to_string(),
+                exit_code: error.exit_code(),
+            },
+        };
+        write_message(output, &response)?;
+    }
+    Ok(())
+}
+
+fn answer(dirs: &Dirs, request: Request) -> Result<Response, Error> {
+    match request {
+        Request::Ping => Ok(Response::Pong {
+            version: env!("CARGO_PKG_VERSION"),
+        }),
+        Request::Code { origin } => {
+            let host = match origin.split_once("://") {
+                Some(("https", _)) => host_of(&origin),
+                _ => None,
+            }
+            .ok_or_else(|| Error::InvalidArgument(format!("unsupported origin {origin}")))?;
+            let time = current_time()?;
+            let (identifiers, _) = list_all_identifiers(dirs)?;
+            let mut matches = vec![];
+            // Entries that fail to decrypt cannot match and are left out.
+            for identifier in identifiers.into_iter().flatten() {
+                let Ok(entry) = read_entry(dirs, &identifier) else {
+                    continue;
+                };
+                if !matches_host(&host, &entry.metadata) {
+                    continue;
+                }
+                let totp = entry.totp()?;
+                matches.push(Match {
+                    identifier,
+                    issuer: entry.metadata.issuer,
+                    account: entry.metadata.account,
+                    totp: totp.generate_at(time)?,
+                    valid_for: totp.valid_for(time),
+                });
+            }
+            Ok(Response::Matches { matches })
+        }
+    }
+}
+
+/// Read the next message, or `None` if the input is closed.
+fn read_message(input: &mut impl Read) -> Result<Option<Vec<u8>>, Error> {
+    let mut length = [0; 4];
+    match input.read_exact(&mut length) {
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.map_err(|err| Error::io("error reading message", err))?,
+    }
+    let length = u32::from_ne_bytes(length);
+    if length > MAX_REQUEST_LENGTH {
+        return Err(Error::InvalidArgument(format!(
+            "message of {length} bytes exceeds {MAX_REQUEST_LENGTH} bytes"
+        )));
+    }
+    let mut message = vec![0; length as usize];
+    input
+        .read_exact(&mut message)
+        .map_err(|err| Error::io("error reading message", err))?;
+    Ok(Some(message))
+}
+
+fn write_message(output: &mut impl Write, response: &Response) -> Result<(), Error> {
+    let message = serde_json::to_vec(response)
+        .map_err(|error| Error::Other(format!("error serializing message - {error}")))?;
+    output
+        .write_all(&(message.len() as u32).to_ne_bytes())
+        .and_then(|()| output.write_all(&message))
+        .and_then(|()| output.flush())
+        .map_err(|err| Error::io("error writing message", err))
+}
+
+/// Lower case host of the URL, which may lack the scheme.
+fn host_of(url: &str) -> Option<String> {
+    let authority = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split(['/', '?', '#'])
+        .next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+        .split(':')
+        .next()?
+        .trim_end_matches('.')
+        .to_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// Whether the entry belongs to the host: its URL is on exactly that host.
+///
+/// Neither parent domains nor the issuer are matched, as they cannot tell a
+/// site from others on the same domain, e. g. `attacker.github.io` from
+/// `github.com` or a taken over subdomain from its parent.
+fn matches_host(host: &str, metadata: &Metadata) -> bool {
+    metadata
+        .url
+        .as_deref()
+        .and_then(host_of)
+        .is_some_and(|url_host| host == url_host)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        dirs::Dirs,
+        entry::Metadata,
+        native_host::{host_of, matches_host, serve},
+    };
+
+    fn message(json: &str) -> Vec<u8> {
+        let mut message = (json.len() as u32).to_ne_bytes().to_vec();
+        message.extend(json.as_bytes());
+        message
+    }
+
+    #[test]
+    fn host_is_extracted_from_url() {
+        assert_eq!(
+            host_of("https://GitHub.com/login"),
+            Some("github.com".into())
+        );
+        assert_eq!(
+            host_of("https://user@sso.example.com:8443?next=/"),
+            Some("sso.example.com".into())
+        );
+        assert_eq!(host_of("example.com"), Some("example.com".into()));
+        assert_eq!(host_of("https://"), None);
+    }
+
+    #[test]
+    fn host_matches_url_only() {
+        let url = |url: &str| Metadata {
+            url: Some(url.to_string()),
+            ..Default::default()
+        };
+        let issuer = |issuer: &str| Metadata {
+            issuer: Some(issuer.to_string()),
+            ..Default::default()
+        };
+
+        assert!(matches_host("github.com", &url("https://github.com/login")));
+        assert!(matches_host("example.com", &url("example.com")));
+        assert!(!matches_host("login.example.com", &url("example.com")));
+        assert!(!matches_host("notexample.com", &url("example.com")));
+        assert!(!matches_host("github.com", &issuer("GitHub")));
+        assert!(!matches_host("attacker.github.io", &issuer("GitHub")));
+        assert!(!matches_host(
+            "attacker.github.io",
+            &url("https://github.com")
+        ));
+        assert!(!matches_host("github.com", &Metadata::default()));
+    }
+
+    #[test]
+    fn serve_answers_each_message() {
+        let dirs = Dirs::new("/nonexistent/store", "/nonexistent/gnupg");
+        let mut input = message(r#"{"action":"ping"}"#);
+        input.extend(message(r#"{"action":"code","origin":"file:///etc"}"#));
+        input.extend(message(r#"{"action":"code","origin":"http://github.com"}"#));
+        let mut output = vec![];
+
+        serve(&dirs, &mut Cursor::new(input), &mut output).unwrap();
+
+        let expected = [
+            message(&format!(r#"{{"version":"{}"}}"#, env!("CARGO_PKG_VERSION"))),
+            message(r#"{"error":"unsupported origin file:///etc","exit_code":2}"#),
+            message(r#"{"error":"unsupported origin http://github.com","exit_code":2}"#),
+        ]
+        .concat();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn serve_rejects_oversized_messages() {
+        let dirs = Dirs::new("/nonexistent/store", "/nonexistent/gnupg");
+        let input = u32::MAX.to_ne_bytes().to_vec();
+
+        assert!(serve(&dirs, &mut Cursor::new(input), &mut vec![]).is_err());
+    }
+}
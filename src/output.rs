@@ -0,0 +1,721 @@
+//! Output of commands in the formats selectable with `--format`.
+//!
+//! The JSON schema is stable; fields are only ever added. Every command
+//! outputs a single JSON object:
+//!
+//! - `init`: `{"gpg_id": string, "folder"?: string, "store_dir": string,
+//!   "store_dir_source": string, "gnupg_home": string, "gnupg_home_source":
+//!   string, "warnings"?: [warning]}`, warning about a change that could not
+//!   be committed to git
+//! - `list`: `{"identifiers": [string], "warnings"?: [warning], "entries"?:
+//!   [entry], "stores"?: [{"name": string, "store_dir": string,
+//!   "identifiers": [string], "warnings"?: [warning], "entries"?: [entry],
+//!   "error"?: string}]}`, where a warning about a skipped file is
+//!   `{"path"?: string, "message": string}` and `list --long` adds an entry
+//!   `{"identifier": string, "issuer"?: string, "account"?: string, "tags"?:
+//!   [string], "algorithm": string, "last_used"?: string}` per identifier
+//!   whose metadata is known
+//! - `find`: `{"matches": [{"identifier": string, "issuer"?: string,
+//!   "account"?: string}]}`, best matches first
+//! - `store`: `{"identifier": string, "stored": true, "warnings"?:
+//!   [warning]}`, warning about changes that could not be committed or
+//!   indexed
+//! - `generate`: `{"identifier": string, "uri": string, "warnings"?:
+//!   [warning]}`, like `store`
+//! - `read`: `{"identifier": string, "key": string, "algorithm": string,
+//!   "digits": number, "period": number, "issuer"?: string, "account"?:
+//!   string, "url"?: string, "tags"?: [string], "counter"?: number,
+//!   "notes"?: string, "created"?: string}`
+//! - `delete`: `{"identifier": string, "deleted": true, "warnings"?:
+//!   [warning]}`, like `store`
+//! - `edit`: `{"identifier": string, "edited": boolean, "warnings"?:
+//!   [warning]}`, like `store`
+//! - `tag add`, `tag remove`: `{"identifier": string, "tags": [string],
+//!   "warnings"?: [warning]}` with the tags of the entry after the change,
+//!   like `store`
+//! - `index`: `{"indexed": number, "warnings"?: [warning]}`
+//! - `mv`: `{"source": string, "target": string, "moved": true, "warnings"?:
+//!   [warning]}`, like `store`
+//! - `cp`: `{"source": string, "target": string, "copied": true,
+//!   "warnings"?: [warning]}`, like `mv`
+//! - `compute`: `{"identifier": string, "totp": string, "time": number,
+//!   "valid_for": number, "next_totp"?: string}`
+//! - `compute --all`: `{"time": number, "valid_for": number, "entries":
+//!   [{"identifier": string, "totp"?: string, "valid_for"?: number,
+//!   "next_totp"?: string, "error"?: string}], "warnings"?: [warning]}`,
+//!   where the top level `valid_for` is the least one of the entries, when
+//!   the first password changes, or the one of the default period of 30
+//!   seconds without any; if any entry has an error, the error below follows
+//!   on stderr
+//! - `verify`: `{"identifier": string, "code": string, "valid": true,
+//!   "offset": number}`
+//! - `menu`: `{"identifier": string, "typed": boolean}`, or nothing if no
+//!   identifier was chosen
+//! - `completions`: `{"script": string}`
+//! - `native-host`: nothing, it speaks the native messaging protocol instead
+//! - `check`: `{"checked": number}` if no problems were found
+//! - errors: `{"error": string, "exit_code": number, "problems"?:
+//!   [warning]}`, where `check` lists the problems it found
+
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute::Algorithm,
+    dirs::{Source, STORE_SEPARATOR},
+    entry::Metadata,
+    error::Error,
+    file::Warning,
+    index::IndexEntry,
+};
+
+const IDENTIFIER_LIST_HEADER: &str = "totp computer\n";
+const IDENTIFIER_LIST_ITEM_PREFIX: &str = "├─";
+const IDENTIFIER_LIST_LAST_ITEM_PREFIX: &str = "└─";
+const COMPUTE_ALL_HEADER: [&str; 4] = ["IDENTIFIER", "TOTP", "VALID FOR", "NEXT"];
+const ERROR_CELL: &str = "error";
+
+/// Format in which command output is rendered.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// JSON object with a stable schema.
+    Json,
+    /// Only the essential value, e. g. the TOTP, without any decoration.
+    Raw,
+}
+
+/// Identifiers of an additional store in `list`.
+#[derive(Debug, Serialize)]
+pub struct StoreEntries {
+    pub name: String,
+    pub store_dir: PathBuf,
+    pub identifiers: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<ListedEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Identifier with the metadata of its entry in `list --long`.
+#[derive(Debug, Serialize)]
+pub struct ListedEntry {
+    pub identifier: String,
+    #[serde(flatten)]
+    pub metadata: IndexEntry,
+}
+
+/// Result of one entry in `compute --all`.
+#[derive(Debug, Serialize)]
+pub struct TotpEntry {
+    pub identifier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_for: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_totp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Identifier matching the pattern of `find`.
+#[derive(Debug, Serialize)]
+pub struct FoundEntry {
+    pub identifier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+/// Result of a command.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Output {
+    Initialized {
+        gpg_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        folder: Option<String>,
+        store_dir: PathBuf,
+        store_dir_source: Source,
+        gnupg_home: PathBuf,
+        gnupg_home_source: Source,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    List {
+        identifiers: Vec<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        entries: Vec<ListedEntry>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        stores: Vec<StoreEntries>,
+    },
+    Found {
+        #[serde(skip)]
+        pattern: String,
+        matches: Vec<FoundEntry>,
+    },
+    Stored {
+        identifier: String,
+        stored: bool,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Generated {
+        identifier: String,
+        uri: String,
+        #[serde(skip)]
+        qr_code: Option<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Key {
+        identifier: String,
+        key: String,
+        algorithm: Algorithm,
+        digits: u32,
+        period: u64,
+        #[serde(flatten)]
+        metadata: Metadata,
+    },
+    Deleted {
+        identifier: String,
+        deleted: bool,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Edited {
+        identifier: String,
+        edited: bool,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Tagged {
+        identifier: String,
+        tags: Vec<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Checked {
+        checked: usize,
+    },
+    Indexed {
+        indexed: usize,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Moved {
+        source: String,
+        target: String,
+        moved: bool,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Copied {
+        source: String,
+        target: String,
+        copied: bool,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Totp {
+        identifier: String,
+        totp: String,
+        time: u64,
+        valid_for: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        next_totp: Option<String>,
+        #[serde(skip)]
+        is_current: bool,
+    },
+    TotpTable {
+        time: u64,
+        valid_for: u64,
+        entries: Vec<TotpEntry>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Warning>,
+    },
+    Verified {
+        identifier: String,
+        code: String,
+        valid: bool,
+        offset: i64,
+    },
+    Script {
+        script: String,
+    },
+    Candidates {
+        candidates: Vec<(String, String)>,
+    },
+    Picked {
+        identifier: String,
+        typed: bool,
+    },
+    /// Output of commands that communicate on stdout themselves.
+    Silent,
+}
+
+impl Output {
+    /// Render output in the given format.
+    pub fn render(&self, format: Format) -> Result<String, Error> {
+        match format {
+            Format::Text => Ok(self.render_text()),
+            Format::Json if matches!(self, Self::Silent) => Ok(String::new()),
+            Format::Json => serde_json::to_string(self)
+                .map_err(|error| Error::Other(format!("error serializing output - {error}"))),
+            Format::Raw => Ok(self.render_raw()),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        match self {
+            Self::Initialized {
+                gpg_id,
+                folder,
+                store_dir,
+                store_dir_source,
+                gnupg_home,
+                gnupg_home_source,
+                warnings,
+            } => {
+                format!(
+                    "{} initialized with gpg id {gpg_id}
+store dir: {} (from {store_dir_source})
+gnupg home: {} (from {gnupg_home_source})",
+                    match folder {
+                        None => "totp computer".to_string(),
+                        Some(folder) => format!("folder {folder}"),
+                    },
+                    store_dir.display(),
+                    gnupg_home.display()
+                ) + &print_warnings(warnings)
+            }
+            Self::List {
+                identifiers,
+                warnings,
+                entries,
+                stores,
+            } => {
+                let identifiers = describe_identifiers(identifiers, entries);
+                let mut output = print_list(IDENTIFIER_LIST_HEADER, &identifiers);
+                output.push_str(&print_warnings(warnings));
+                for store in stores {
+                    let header = format!("{} ({})\n", store.name, store.store_dir.display());
+                    let identifiers = describe_identifiers(&store.identifiers, &store.entries);
+                    output.push_str("\n\n");
+                    match &store.error {
+                        None => output.push_str(&print_list(&header, &identifiers)),
+                        Some(error) => output.push_str(&format!("{header}error: {error}")),
+                    }
+                    output.push_str(&print_warnings(&store.warnings));
+                }
+                output
+            }
+            Self::Found { pattern, matches } if matches.is_empty() => {
+                format!("No identifier matches {pattern}.")
+            }
+            Self::Found { matches, .. } => matches
+                .iter()
+                .map(|found| {
+                    let details = [&found.issuer, &found.account]
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    match details.is_empty() {
+                        true => found.identifier.clone(),
+                        false => format!("{} ({})", found.identifier, details.join(", ")),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Stored {
+                identifier,
+                warnings,
+                ..
+            } => format!("Key for {identifier} stored.") + &print_warnings(warnings),
+            Self::Generated {
+                identifier,
+                uri,
+                qr_code,
+                warnings,
+            } => {
+                let mut output = format!("Key for {identifier} generated and stored.\n{uri}");
+                if let Some(qr_code) = qr_code {
+                    output.push('\n');
+                    output.push_str(qr_code);
+                }
+                output.push_str(&print_warnings(warnings));
+                output
+            }
+            Self::Key {
+                identifier,
+                key,
+                algorithm,
+                digits,
+                period,
+                metadata,
+            } => {
+                let mut output = format!(
+                    "Key for {identifier}: {key} ({algorithm}, {digits} digits, {period}s period)"
+                );
+                for (name, value) in [
+                    ("issuer", metadata.issuer.clone()),
+                    ("account", metadata.account.clone()),
+                    ("url", metadata.url.clone()),
+                    (
+                        "tags",
+                        Some(metadata.tags.join(", ")).filter(|tags| !tags.is_empty()),
+                    ),
+                    (
+                        "counter",
+                        metadata.counter.map(|counter| counter.to_string()),
+                    ),
+                    ("created", metadata.created.clone()),
+                    ("notes", metadata.notes.clone()),
+                ] {
+                    if let Some(value) = value {
+                        output.push_str(&format!("\n{name}: {value}"));
+                    }
+                }
+                output
+            }
+            Self::Deleted {
+                identifier,
+                warnings,
+                ..
+            } => format!("Key for {identifier} deleted.") + &print_warnings(warnings),
+            Self::Edited {
+                identifier,
+                edited,
+                warnings,
+            } => {
+                let output = match edited {
+                    true => format!("Key for {identifier} edited."),
+                    false => format!("Key for {identifier} unchanged."),
+                };
+                output + &print_warnings(warnings)
+            }
+            Self::Checked { checked } => {
+                format!("Checked {checked} entries, no problems found.")
+            }
+            Self::Indexed { indexed, warnings } => {
+                let mut output = format!("Indexed {indexed} entries.");
+                output.push_str(&print_warnings(warnings));
+                output
+            }
+            Self::Tagged {
+                identifier,
+                tags,
+                warnings,
+            } => {
+                let output = match tags.as_slice() {
+                    [] => format!("Key for {identifier} has no tags."),
+                    tags => format!("Key for {identifier} tagged {}.", tags.join(", ")),
+                };
+                output + &print_warnings(warnings)
+            }
+            Self::Moved {
+                source,
+                target,
+                warnings,
+                ..
+            } => format!("Key for {source} moved to {target}.") + &print_warnings(warnings),
+            Self::Copied {
+                source,
+                target,
+                warnings,
+                ..
+            } => format!("Key for {source} copied to {target}.") + &print_warnings(warnings),
+            Self::Totp {
+                identifier,
+                totp,
+                time,
+                valid_for,
+                next_totp,
+                is_current,
+            } => {
+                let mut output = if *is_current {
+                    format!("Current TOTP for {identifier} is {totp} (valid for {valid_for}s)")
+                } else {
+                    format!("TOTP for {identifier} at {time} is {totp} (valid for {valid_for}s)")
+                };
+                if let Some(next_totp) = next_totp {
+                    output.push_str(&format!("\nNext TOTP for {identifier} is {next_totp}"));
+                }
+                output
+            }
+            Self::TotpTable {
+                valid_for,
+                entries,
+                warnings,
+                ..
+            } => print_table(entries, *valid_for) + &print_warnings(warnings),
+            Self::Verified {
+                identifier,
+                code,
+                offset,
+                ..
+            } => format!(
+                "Code {code} is valid for {identifier} (matched time step offset {offset:+})"
+            ),
+            Self::Picked { identifier, typed } => match typed {
+                true => format!("TOTP for {identifier} typed."),
+                false => format!("TOTP for {identifier} copied to clipboard."),
+            },
+            Self::Script { script } => script.clone(),
+            Self::Candidates { candidates } => print_candidates(candidates),
+            Self::Silent => String::new(),
+        }
+    }
+
+    fn render_raw(&self) -> String {
+        match self {
+            Self::Initialized { .. }
+            | Self::Stored { .. }
+            | Self::Deleted { .. }
+            | Self::Edited { .. }
+            | Self::Tagged { .. }
+            | Self::Indexed { .. }
+            | Self::Checked { .. }
+            | Self::Moved { .. }
+            | Self::Copied { .. }
+            | Self::Picked { .. } => String::new(),
+            Self::List {
+                identifiers,
+                stores,
+                ..
+            } => identifiers
+                .iter()
+                .cloned()
+                .chain(stores.iter().flat_map(|store| {
+                    store
+                        .identifiers
+                        .iter()
+                        .map(|identifier| format!("{}{STORE_SEPARATOR}{identifier}", store.name))
+                }))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Generated { uri, .. } => uri.clone(),
+            Self::Key { key, .. } => key.clone(),
+            Self::Totp {
+                totp, next_totp, ..
+            } => match next_totp {
+                None => totp.clone(),
+                Some(next_totp) => format!("{totp}\n{next_totp}"),
+            },
+            Self::TotpTable { entries, .. } => entries
+                .iter()
+                .map(|entry| {
+                    let totp = entry.totp.as_deref().unwrap_or_default();
+                    match &entry.next_totp {
+                        None => format!("{}\t{totp}", entry.identifier),
+                        Some(next_totp) => format!("{}\t{totp}\t{next_totp}", entry.identifier),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Found { matches, .. } => matches
+                .iter()
+                .map(|found| found.identifier.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Verified { offset, .. } => offset.to_string(),
+            Self::Script { script } => script.clone(),
+            Self::Candidates { candidates } => print_candidates(candidates),
+            Self::Silent => String::new(),
+        }
+    }
+}
+
+/// Render an error in the given format.
+pub fn render_error(error: &Error, format: Format) -> String {
+    match format {
+        Format::Json => {
+            let mut json = serde_json::json!({
+                "error": error.to_string(),
+                "exit_code": error.exit_code(),
+            });
+            if let Error::StoreProblems { problems, .. } = error {
+                json["problems"] = serde_json::json!(problems);
+            }
+            json.to_string()
+        }
+        Format::Text | Format::Raw => format!("Error: {error}"),
+    }
+}
+
+fn print_list(header: &str, identifier_list: &[String]) -> String {
+    let mut printed_list = String::from(header);
+    if let Some((last_identifier, identifiers)) = identifier_list.split_last() {
+        for identifier in identifiers {
+            printed_list.push_str(format!("{IDENTIFIER_LIST_ITEM_PREFIX} {identifier}\n").as_str())
+        }
+        printed_list
+            .push_str(format!("{IDENTIFIER_LIST_LAST_ITEM_PREFIX} {}", last_identifier).as_str());
+    } else {
+        printed_list.push_str("--- empty ---")
+    }
+    printed_list
+}
+
+/// Warnings as lines to append to text output, each starting with a line
+/// break.
+fn print_warnings(warnings: &[Warning]) -> String {
+    warnings
+        .iter()
+        .map(|warning| format!("\nwarning: {warning}"))
+        .collect()
+}
+
+/// Identifiers followed by the metadata of their entries, if known.
+fn describe_identifiers(identifiers: &[String], entries: &[ListedEntry]) -> Vec<String> {
+    identifiers
+        .iter()
+        .map(|identifier| {
+            let Some(entry) = entries.iter().find(|entry| &entry.identifier == identifier) else {
+                return identifier.clone();
+            };
+            let metadata = &entry.metadata;
+            let mut details = [&metadata.issuer, &metadata.account]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>();
+            details.push(metadata.algorithm.to_string());
+            if !metadata.tags.is_empty() {
+                details.push(format!("tags: {}", metadata.tags.join(" ")));
+            }
+            if let Some(last_used) = &metadata.last_used {
+                details.push(format!("last used {last_used}"));
+            }
+            format!("{identifier} - {}", details.join(", "))
+        })
+        .collect()
+}
+
+fn print_candidates(candidates: &[(String, String)]) -> String {
+    candidates
+        .iter()
+        .map(|(value, description)| format!("{value}\t{description}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn print_table(entries: &[TotpEntry], valid_for: u64) -> String {
+    if entries.is_empty() {
+        return "--- empty ---".to_string();
+    }
+    let show_next = entries.iter().any(|entry| entry.next_totp.is_some());
+    let mut header = COMPUTE_ALL_HEADER.map(String::from);
+    if !show_next {
+        header[3] = String::new();
+    }
+    let rows: Vec<[String; 4]> = entries
+        .iter()
+        .map(|entry| match (&entry.totp, &entry.error) {
+            (Some(totp), _) => [
+                entry.identifier.clone(),
+                totp.clone(),
+                format!("{}s", entry.valid_for.unwrap_or(valid_for)),
+                entry.next_totp.clone().unwrap_or_default(),
+            ],
+            (None, error) => [
+                entry.identifier.clone(),
+                ERROR_CELL.to_string(),
+                error.clone().unwrap_or_default(),
+                String::new(),
+            ],
+        })
+        .collect();
+    let column_width = |column: usize| {
+        rows.iter()
+            .chain(std::iter::once(&header))
+            .map(|row| row[column].chars().count())
+            .max()
+            .unwrap_or_default()
+    };
+    let width = column_width(0);
+    // codes have 6 to 8 digits, the error cell is shorter
+    let totp_width = column_width(1).max(6);
+    let mut table = vec![];
+    for [identifier, totp, validity, next_totp] in std::iter::once(&header).chain(&rows) {
+        let line = format!("{identifier:width$}  {totp:totp_width$}  {validity:10}  {next_totp}");
+        table.push(line.trim_end().to_string());
+    }
+    let failed_count = entries.iter().filter(|entry| entry.error.is_some()).count();
+    if failed_count > 0 {
+        table.push(format!(
+            "{failed_count} of {} entries failed",
+            entries.len()
+        ));
+    }
+    table.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{
+        print_list, Format, Output, IDENTIFIER_LIST_HEADER, IDENTIFIER_LIST_ITEM_PREFIX,
+        IDENTIFIER_LIST_LAST_ITEM_PREFIX,
+    };
+
+    #[test]
+    fn print_identifier_list() {
+        let identifiers = vec![
+            String::from("identifier_1"),
+            String::from("identifier_2"),
+            String::from("identifier_3"),
+        ];
+        let mut expected_printed_list = IDENTIFIER_LIST_HEADER.to_string();
+        identifiers[0..identifiers.len() - 1]
+            .iter()
+            .for_each(|identifier| {
+                expected_printed_list
+                    .push_str(format!("{IDENTIFIER_LIST_ITEM_PREFIX} {identifier}\n").as_str())
+            });
+        expected_printed_list.push_str(
+            format!(
+                "{IDENTIFIER_LIST_LAST_ITEM_PREFIX} {}",
+                identifiers.last().unwrap()
+            )
+            .as_str(),
+        );
+
+        let printed_list = print_list(IDENTIFIER_LIST_HEADER, &identifiers);
+
+        assert_eq!(printed_list, expected_printed_list);
+    }
+
+    #[test]
+    fn render_totp_in_all_formats() {
+        let output = Output::Totp {
+            identifier: "test_identifier".to_string(),
+            totp: "081804".to_string(),
+            time: 1111111109,
+            valid_for: 1,
+            next_totp: None,
+            is_current: true,
+        };
+
+        assert_eq!(
+            output.render(Format::Text).unwrap(),
+            "Current TOTP for test_identifier is 081804 (valid for 1s)"
+        );
+        assert_eq!(
+            output.render(Format::Json).unwrap(),
+            r#"{"identifier":"test_identifier","totp":"081804","time":1111111109,"valid_for":1}"#
+        );
+        assert_eq!(output.render(Format::Raw).unwrap(), "081804");
+    }
+}
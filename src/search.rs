@@ -0,0 +1,100 @@
+use std::mem::discriminant;
+
+use crate::dirs::STORE_SEPARATOR;
+
+/// How well a text matches a search pattern, ignoring case. Better matches
+/// compare less.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Rank {
+    /// Text equals the pattern.
+    Exact,
+    /// Text starts with the pattern.
+    Prefix,
+    /// Text contains the pattern.
+    Substring,
+    /// Characters of the pattern occur in order in the text, with the given
+    /// number of other characters between them.
+    Fuzzy(usize),
+}
+
+impl Rank {
+    /// Whether both ranks are of the same kind, regardless of the distance of
+    /// fuzzy matches.
+    pub fn is_same_kind(&self, other: &Rank) -> bool {
+        discriminant(self) == discriminant(other)
+    }
+}
+
+/// Rank of the text for the pattern, or `None` if it does not match.
+pub fn rank(pattern: &str, text: &str) -> Option<Rank> {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    if text == pattern {
+        Some(Rank::Exact)
+    } else if text.starts_with(&pattern) {
+        Some(Rank::Prefix)
+    } else if text.contains(&pattern) {
+        Some(Rank::Substring)
+    } else {
+        fuzzy_distance(&pattern, &text).map(Rank::Fuzzy)
+    }
+}
+
+/// Best rank of the identifier for the pattern, also matching the identifier
+/// without the store name of a qualified identifier, e. g. `aws-root` of
+/// `team:aws-root`.
+pub fn rank_identifier(pattern: &str, identifier: &str) -> Option<Rank> {
+    let name = identifier.split_once(STORE_SEPARATOR).map(|(_, name)| name);
+    [Some(identifier), name]
+        .into_iter()
+        .flatten()
+        .filter_map(|text| rank(pattern, text))
+        .min()
+}
+
+/// Number of characters between the first and last character of the
+/// pattern in the text, matching its characters in order as early as
+/// possible.
+fn fuzzy_distance(pattern: &str, text: &str) -> Option<usize> {
+    let mut pattern_chars = pattern.chars().peekable();
+    let mut start = None;
+    let mut gaps = 0;
+    for (index, char) in text.chars().enumerate() {
+        let Some(next) = pattern_chars.peek() else {
+            break;
+        };
+        if *next == char {
+            pattern_chars.next();
+            start.get_or_insert(index);
+        } else if start.is_some() {
+            gaps += 1;
+        }
+    }
+    pattern_chars.peek().is_none().then_some(gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::{rank, rank_identifier, Rank};
+
+    #[test]
+    fn rank_prefers_closer_matches() {
+        assert_eq!(rank("GitHub", "github"), Some(Rank::Exact));
+        assert_eq!(rank("git", "github"), Some(Rank::Prefix));
+        assert_eq!(rank("hub", "github"), Some(Rank::Substring));
+        assert_eq!(rank("ghb", "github"), Some(Rank::Fuzzy(3)));
+        assert_eq!(rank("gtx", "github"), None);
+        assert!(Rank::Fuzzy(1) < Rank::Fuzzy(3));
+        assert!(Rank::Fuzzy(1).is_same_kind(&Rank::Fuzzy(3)));
+        assert!(!Rank::Prefix.is_same_kind(&Rank::Substring));
+    }
+
+    #[test]
+    fn qualified_identifier_matches_by_name() {
+        assert_eq!(rank_identifier("aws", "team:aws-root"), Some(Rank::Prefix));
+        assert_eq!(
+            rank_identifier("team:", "team:aws-root"),
+            Some(Rank::Prefix)
+        );
+    }
+}
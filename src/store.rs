@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    entry::Entry,
+    error::Error,
+    file::{
+        delete_key_file, init, key_file_exists, list_identifiers, read_decrypted_key_from_file,
+        read_gpg_id, replace_encrypted_key_in_file, Listing,
+    },
+    index::reindex,
+};
+
+/// Directory of GPG encrypted entries, each in a file `<identifier>.gpg`,
+/// that are encrypted for the GPG id in its `.gpg-id` file. Identifiers of
+/// entries in folders separate them with `/`, and entries in folders with a
+/// `.gpg-id` file of their own are encrypted for that GPG id instead.
+///
+/// ```no_run
+/// use totpc::{Entry, Store};
+///
+/// let store = Store::open("/home/alice/.totpc", "/home/alice/.gnupg")?;
+/// store.put("example", &Entry::new("JBSWY3DPEHPK3PXP"))?;
+/// for identifier in store.list()?.identifiers {
+///     let totp = store.get(&identifier)?.totp()?;
+///     println!("{identifier}: {}", totp.generate_at(1111111109)?);
+/// }
+/// # Ok::<(), totpc::Error>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Store {
+    dir: PathBuf,
+    gnupg_home: PathBuf,
+}
+
+impl Store {
+    /// Open the initialized store in the given directory, using the given
+    /// GnuPG home directory for encryption and decryption.
+    pub fn open(dir: impl Into<PathBuf>, gnupg_home: impl Into<PathBuf>) -> Result<Self, Error> {
+        let store = Self {
+            dir: dir.into(),
+            gnupg_home: gnupg_home.into(),
+        };
+        store.gpg_id()?;
+        Ok(store)
+    }
+
+    /// Initialize a store in the given directory with the GPG id to encrypt
+    /// entries for, and open it.
+    pub fn init(
+        dir: impl Into<PathBuf>,
+        gnupg_home: impl Into<PathBuf>,
+        gpg_id: &str,
+    ) -> Result<Self, Error> {
+        let dir = dir.into();
+        init(&dir, gpg_id)?;
+        Self::open(dir, gnupg_home)
+    }
+
+    /// Directory of the store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// GPG id that entries are encrypted for, unless their folder has one.
+    pub fn gpg_id(&self) -> Result<String, Error> {
+        read_gpg_id(&self.dir)
+    }
+
+    /// Identifiers of all entries in ascending order, with warnings about
+    /// files that could not be read.
+    pub fn list(&self) -> Result<Listing, Error> {
+        list_identifiers(&self.dir)
+    }
+
+    /// Whether an entry with the given identifier exists.
+    pub fn contains(&self, identifier: &str) -> Result<bool, Error> {
+        key_file_exists(&self.dir, identifier)
+    }
+
+    /// Decrypt the entry with the given identifier. GnuPG may ask for the
+    /// passphrase through its pinentry program.
+    pub fn get(&self, identifier: &str) -> Result<Entry, Error> {
+        self.decrypt(identifier, true)
+    }
+
+    /// Decrypt the entry with the given identifier like [`Store::get`], but
+    /// fail instead of asking for the passphrase if GnuPG has not cached it.
+    pub fn get_cached(&self, identifier: &str) -> Result<Entry, Error> {
+        self.decrypt(identifier, false)
+    }
+
+    /// Encrypt and save the entry with the given identifier, replacing an
+    /// existing one, and update the index of the store if it has one.
+    pub fn put(&self, identifier: &str, entry: &Entry) -> Result<(), Error> {
+        replace_encrypted_key_in_file(
+            &self.gnupg_home,
+            &self.dir,
+            identifier,
+            &entry.to_payload()?,
+        )?;
+        reindex(&self.gnupg_home, &self.dir, |index| {
+            index.set(identifier, entry)
+        });
+        Ok(())
+    }
+
+    /// Delete the entry with the given identifier, also from the index of the
+    /// store if it has one.
+    pub fn remove(&self, identifier: &str) -> Result<(), Error> {
+        delete_key_file(&self.dir, identifier)?;
+        reindex(&self.gnupg_home, &self.dir, |index| {
+            index.entries.remove(identifier);
+        });
+        Ok(())
+    }
+
+    fn decrypt(&self, identifier: &str, interactive: bool) -> Result<Entry, Error> {
+        match read_decrypted_key_from_file(&self.gnupg_home, &self.dir, identifier, interactive)? {
+            None => Err(Error::EntryNotFound(identifier.to_string())),
+            Some(payload) => Entry::parse(&payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use tempfile::TempDir;
+
+    use crate::{store::Store, Error};
+
+    #[test]
+    fn open_fails_for_uninitialized_store() {
+        let dir = TempDir::new().unwrap();
+
+        let result = Store::open(dir.path(), dir.path());
+
+        assert!(matches!(result, Err(Error::NotInitialized(_))));
+    }
+
+    #[test]
+    fn list_and_remove_entries() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::init(dir.path().join("store"), dir.path(), "Test Man").unwrap();
+        write(store.dir().join("test_id_2.gpg"), "").unwrap();
+        write(store.dir().join("test_id_1.gpg"), "").unwrap();
+        write(store.dir().join(".index.gpg"), "").unwrap();
+
+        assert_eq!(store.gpg_id().unwrap(), "Test Man");
+        assert_eq!(
+            store.list().unwrap().identifiers,
+            vec!["test_id_1", "test_id_2"]
+        );
+
+        store.remove("test_id_1").unwrap();
+        assert!(!store.contains("test_id_1").unwrap());
+        assert!(matches!(
+            store.remove("test_id_1"),
+            Err(Error::EntryNotFound(_))
+        ));
+        assert!(matches!(
+            store.get("test_id_1"),
+            Err(Error::EntryNotFound(_))
+        ));
+    }
+}
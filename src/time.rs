@@ -0,0 +1,212 @@
+use crate::error::Error;
+
+/// Parse a point in time given as Unix time in seconds or as RFC 3339
+/// timestamp (e. g. "2023-05-01T12:00:00Z") to Unix time in seconds.
+pub fn parse_time(value: &str) -> Result<u64, Error> {
+    if let Ok(time) = value.parse::<u64>() {
+        return Ok(time);
+    }
+    parse_rfc3339(value).ok_or_else(|| {
+        Error::InvalidArgument(format!(
+            "invalid time \"{value}\" - must be Unix time or RFC 3339 timestamp"
+        ))
+    })
+}
+
+/// Parse a time offset in seconds with optional sign, e. g. "+30" or "-90".
+pub fn parse_offset(value: &str) -> Result<i64, Error> {
+    value
+        .strip_prefix('+')
+        .unwrap_or(value)
+        .parse::<i64>()
+        .map_err(|_| {
+            Error::InvalidArgument(format!(
+                "invalid offset \"{value}\" - must be seconds like +30 or -90"
+            ))
+        })
+}
+
+/// Apply an offset in seconds to the given Unix time in seconds.
+pub fn apply_offset(time: u64, offset: i64) -> Result<u64, Error> {
+    time.checked_add_signed(offset).ok_or_else(|| {
+        Error::InvalidArgument(format!("offset {offset} is out of range for time {time}"))
+    })
+}
+
+/// Format Unix time in seconds as RFC 3339 timestamp in UTC, e. g.
+/// "2023-05-01T12:00:00Z".
+pub fn format_rfc3339(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let seconds = time % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn parse_rfc3339(value: &str) -> Option<u64> {
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = value.get(range)?;
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let year = number(0..4)?;
+    let month = number(5..7)?;
+    let day = number(8..10)?;
+    let hour = number(11..13)?;
+    let minute = number(14..16)?;
+    let second = number(17..19)?;
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // skip fractional seconds
+    let mut rest = &value[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let fraction_length = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if fraction_length == 0 {
+            return None;
+        }
+        rest = &fraction[fraction_length..];
+    }
+    let utc_offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            if rest.len() != 6 || rest.as_bytes()[3] != b':' {
+                return None;
+            }
+            let offset_digits = |range: std::ops::Range<usize>| -> Option<i64> {
+                let digits = rest.get(range)?;
+                if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return None;
+                }
+                digits.parse().ok()
+            };
+            let offset_hours = offset_digits(1..3)?;
+            let offset_minutes = offset_digits(4..6)?;
+            if offset_hours > 23 || offset_minutes > 59 {
+                return None;
+            }
+            sign * (offset_hours * 3600 + offset_minutes * 60)
+        }
+    };
+
+    let time =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - utc_offset;
+    u64::try_from(time).ok()
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Number of days since 1970-01-01 of the given date in the proleptic
+/// Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Date in the proleptic Gregorian calendar of the given number of days since
+/// 1970-01-01, the inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::time::{apply_offset, format_rfc3339, parse_offset, parse_time};
+
+    #[test]
+    fn parse_unix_time() {
+        assert_eq!(parse_time("1111111109").unwrap(), 1111111109);
+    }
+
+    #[test]
+    fn parse_rfc3339_time() {
+        assert_eq!(parse_time("1970-01-01T00:00:00Z").unwrap(), 0);
+        assert_eq!(parse_time("2005-03-18T01:58:29Z").unwrap(), 1111111109);
+        assert_eq!(parse_time("2005-03-18T01:58:29.5Z").unwrap(), 1111111109);
+        assert_eq!(parse_time("2005-03-18T03:58:29+02:00").unwrap(), 1111111109);
+        assert_eq!(parse_time("2024-02-29T00:00:00Z").unwrap(), 1709164800);
+    }
+
+    #[test]
+    fn format_rfc3339_time() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(1111111109), "2005-03-18T01:58:29Z");
+        assert_eq!(format_rfc3339(1709164800), "2024-02-29T00:00:00Z");
+        assert_eq!(parse_time(&format_rfc3339(4102444799)).unwrap(), 4102444799);
+    }
+
+    #[test]
+    fn parse_invalid_time_fails() {
+        assert!(parse_time("2023-02-29T00:00:00Z").is_err());
+        assert!(parse_time("2023-01-01").is_err());
+        assert!(parse_time("2023-01-01T00:00:00").is_err());
+        assert!(parse_time("1969-12-31T23:59:59Z").is_err());
+        assert!(parse_time("-1").is_err());
+    }
+
+    #[test]
+    fn parse_and_apply_offset() {
+        assert_eq!(parse_offset("+30").unwrap(), 30);
+        assert_eq!(parse_offset("-90").unwrap(), -90);
+        assert!(parse_offset("abc").is_err());
+        assert_eq!(apply_offset(100, -90).unwrap(), 10);
+        assert!(apply_offset(10, -90).is_err());
+    }
+}
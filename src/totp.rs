@@ -0,0 +1,162 @@
+use crate::{
+    base32::decode,
+    compute::{compute, seconds_remaining, time_step, verify, Algorithm, DIGITS},
+    entry::Entry,
+    error::Error,
+};
+
+/// Generator of time-based one time passwords according to RFC 6238.
+///
+/// ```
+/// use totpc::{Algorithm, Totp};
+///
+/// let totp = Totp::new(b"12345678901234567890".to_vec(), Algorithm::Sha1, 8, 30).unwrap();
+/// assert_eq!(totp.generate_at(59).unwrap(), "94287082");
+/// assert_eq!(totp.verify("94287082", 89, 1).unwrap(), Some(-1));
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct Totp {
+    key: Vec<u8>,
+    algorithm: Algorithm,
+    digits: u32,
+    period: u64,
+}
+
+impl Totp {
+    /// TOTP of the given plain text key, hash algorithm, number of digits and
+    /// time step length in seconds. Fails for unsupported digits or a period
+    /// of 0.
+    pub fn new(
+        key: Vec<u8>,
+        algorithm: Algorithm,
+        digits: u32,
+        period: u64,
+    ) -> Result<Self, Error> {
+        if !DIGITS.contains(&digits) {
+            return Err(Error::InvalidArgument(format!(
+                "unsupported number of digits {digits} - must be one of {DIGITS:?}"
+            )));
+        }
+        if period == 0 {
+            return Err(Error::InvalidArgument(
+                "period must be greater than 0".to_string(),
+            ));
+        }
+        Ok(Self {
+            key,
+            algorithm,
+            digits,
+            period,
+        })
+    }
+
+    /// TOTP of the given Base32 encoded secret with the default parameters
+    /// SHA1, 6 digits and a period of 30 seconds.
+    pub fn from_base32(secret: &str) -> Result<Self, Error> {
+        Self::try_from(&Entry::new(secret))
+    }
+
+    /// Hash algorithm of the HMAC.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Number of digits of the passwords.
+    pub fn digits(&self) -> u32 {
+        self.digits
+    }
+
+    /// Length of a time step in seconds.
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// Password at the given Unix time in seconds.
+    pub fn generate_at(&self, time: u64) -> Result<String, Error> {
+        compute(
+            &self.key,
+            time_step(time, self.period),
+            self.algorithm,
+            self.digits,
+        )
+    }
+
+    /// Password of the time step after the one of the given Unix time in
+    /// seconds.
+    pub fn generate_next_at(&self, time: u64) -> Result<String, Error> {
+        self.generate_at(time.saturating_add(self.period))
+    }
+
+    /// Seconds that the password of the given Unix time in seconds remains
+    /// valid.
+    pub fn valid_for(&self, time: u64) -> u64 {
+        seconds_remaining(time, self.period)
+    }
+
+    /// Verify the code against the password at the given Unix time in seconds
+    /// and `window` time steps before and after it. Returns the offset of the
+    /// matching time step, if any. Codes are compared in constant time.
+    pub fn verify(&self, code: &str, time: u64, window: u64) -> Result<Option<i64>, Error> {
+        verify(
+            &self.key,
+            time_step(time, self.period),
+            code,
+            window,
+            self.algorithm,
+            self.digits,
+        )
+    }
+}
+
+impl TryFrom<&Entry> for Totp {
+    type Error = Error;
+
+    fn try_from(entry: &Entry) -> Result<Self, Self::Error> {
+        Self::new(
+            decode(&entry.secret)?,
+            entry.algorithm,
+            entry.digits,
+            entry.period,
+        )
+    }
+}
+
+/// Debug output without the key.
+impl std::fmt::Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Totp")
+            .field("algorithm", &self.algorithm)
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compute::Algorithm, totp::Totp};
+
+    #[test]
+    fn generate_rfc_6238_test_vectors() {
+        let totp = Totp::from_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+
+        assert_eq!(totp.generate_at(59).unwrap(), "287082");
+        assert_eq!(totp.generate_at(1111111109).unwrap(), "081804");
+        assert_eq!(totp.generate_next_at(1111111079).unwrap(), "081804");
+        assert_eq!(totp.valid_for(1111111109), 1);
+    }
+
+    #[test]
+    fn invalid_parameters_fail() {
+        assert!(Totp::new(vec![1], Algorithm::Sha1, 5, 30).is_err());
+        assert!(Totp::new(vec![1], Algorithm::Sha1, 6, 0).is_err());
+        assert!(Totp::from_base32("1").is_err());
+    }
+
+    #[test]
+    fn debug_output_omits_key() {
+        let totp = Totp::from_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+
+        assert!(!format!("{totp:?}").contains("49"));
+    }
+}
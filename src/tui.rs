@@ -0,0 +1,526 @@
+//! Full-screen terminal interface listing the live passwords of all entries.
+//!
+//! Entries are grouped by store and folder and decrypted lazily when they
+//! scroll into view, one per refresh, so that the interface stays responsive.
+//! Only the first entry may ask for the passphrase, before the interface
+//! shows, the others fail unless GnuPG has cached the passphrase. Typing
+//! filters the identifiers, Enter copies the password of the selected entry
+//! and Esc clears the filter or quits.
+
+use std::{
+    io::{stdout, Stdout, Write},
+    time::Duration,
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{poll, read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, size, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+
+use crate::{
+    config::Settings,
+    current_time,
+    dirs::{Dirs, STORE_SEPARATOR},
+    error::Error,
+    list_all_identifiers,
+    menu::{clear_later, send, Tools},
+    read_entry,
+    store::Store,
+    totp::Totp,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+const BAR_WIDTH: usize = 10;
+const DEFAULT_GROUP: &str = "default";
+const HELP: &str = "type to filter  ↑↓ select  enter copy  esc clear/quit";
+
+/// Entry of the list, decrypted once it has been visible.
+struct Row {
+    identifier: String,
+    group: String,
+    totp: Option<Result<Totp, String>>,
+}
+
+/// Line of the list, either the header of a group or an entry.
+#[derive(Debug, PartialEq)]
+enum Line {
+    Group(String),
+    Entry(usize),
+}
+
+/// State of the interface.
+struct App {
+    rows: Vec<Row>,
+    filter: String,
+    /// Index of the selected entry among the filtered ones.
+    selected: usize,
+    /// Index of the first line shown.
+    offset: usize,
+    status: String,
+}
+
+/// Restores the terminal when dropped, also on errors.
+struct Screen(Stdout);
+
+impl Screen {
+    fn enter() -> Result<Self, Error> {
+        enable_raw_mode().map_err(|err| Error::io("error setting up terminal", err))?;
+        let mut stdout = stdout();
+        queue!(stdout, EnterAlternateScreen, Hide)
+            .and_then(|()| stdout.flush())
+            .map_err(|err| Error::io("error setting up terminal", err))?;
+        Ok(Self(stdout))
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = queue!(self.0, Show, LeaveAlternateScreen).and_then(|()| self.0.flush());
+        let _ = disable_raw_mode();
+    }
+}
+
+/// Run the interface until the user quits.
+pub fn run(dirs: &Dirs, settings: &Settings) -> Result<(), Error> {
+    let (identifiers, _) = list_all_identifiers(dirs)?;
+    let mut identifiers = identifiers.into_iter().flatten().collect::<Vec<_>>();
+    // keep the entries of each group together
+    identifiers.sort_by(|a, b| location_of(dirs, a).cmp(&location_of(dirs, b)));
+    let mut app = App {
+        rows: identifiers
+            .into_iter()
+            .map(|identifier| Row {
+                group: group_of(location_of(dirs, &identifier)),
+                identifier,
+                totp: None,
+            })
+            .collect(),
+        filter: String::new(),
+        selected: 0,
+        offset: 0,
+        status: HELP.to_string(),
+    };
+    // Decrypt the first entry before entering the screen, so that a
+    // passphrase prompt of gpg shows on the plain terminal.
+    if let Some(row) = app.rows.first_mut() {
+        row.totp = Some(
+            read_entry(dirs, &row.identifier)
+                .and_then(|entry| entry.totp())
+                .map_err(|error| error.to_string()),
+        );
+    }
+    let tools = Tools::new(
+        None,
+        settings.clipboard.clone(),
+        None,
+        settings.paste.clone(),
+    );
+    let mut screen = Screen::enter()?;
+    loop {
+        let (width, height) = size().map_err(|err| Error::io("error reading terminal", err))?;
+        let list_height = usize::from(height).saturating_sub(2);
+        let lines = app.lines();
+        app.scroll(&lines, list_height);
+        draw(&mut screen.0, &app, &lines, width, list_height)?;
+
+        // Decrypt one visible entry per refresh, without waiting for input.
+        let pending = lines
+            .iter()
+            .skip(app.offset)
+            .take(list_height)
+            .find_map(|line| match line {
+                Line::Entry(index) if app.rows[*index].totp.is_none() => Some(*index),
+                _ => None,
+            });
+        let timeout = match pending {
+            Some(index) => {
+                let row = &mut app.rows[index];
+                row.totp = Some(load(dirs, &row.identifier));
+                Duration::ZERO
+            }
+            None => REFRESH_INTERVAL,
+        };
+        if !poll(timeout).map_err(|err| Error::io("error reading terminal", err))? {
+            continue;
+        }
+        let Event::Key(key) = read().map_err(|err| Error::io("error reading terminal", err))?
+        else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match key {
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => return Ok(()),
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } if app.filter.is_empty() => return Ok(()),
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } => app.set_filter(String::new()),
+            KeyEvent {
+                code: KeyCode::Up, ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => app.selected = app.selected.saturating_sub(1),
+            KeyEvent {
+                code: KeyCode::Down,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => app.selected = (app.selected + 1).min(app.filtered().len().saturating_sub(1)),
+            KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            } => {
+                let mut filter = app.filter.clone();
+                filter.pop();
+                app.set_filter(filter);
+            }
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => app.status = app.copy_selected(dirs, &tools, settings),
+            KeyEvent {
+                code: KeyCode::Char(char),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => app.set_filter(format!("{}{char}", app.filter)),
+            _ => {}
+        }
+    }
+}
+
+impl App {
+    /// Indexes of the rows whose identifier contains the filter, ignoring
+    /// case.
+    fn filtered(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        (0..self.rows.len())
+            .filter(|index| {
+                self.rows[*index]
+                    .identifier
+                    .to_lowercase()
+                    .contains(&filter)
+            })
+            .collect()
+    }
+
+    /// Lines of the filtered rows, with a header before each group.
+    fn lines(&self) -> Vec<Line> {
+        let mut lines = vec![];
+        for index in self.filtered() {
+            let group = &self.rows[index].group;
+            if !matches!(lines.last(), Some(Line::Entry(last)) if &self.rows[*last].group == group)
+            {
+                lines.push(Line::Group(group.clone()));
+            }
+            lines.push(Line::Entry(index));
+        }
+        lines
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    /// Index of the line of the selected entry.
+    fn selected_line(&self, lines: &[Line]) -> Option<usize> {
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matches!(line, Line::Entry(_)))
+            .nth(self.selected)
+            .map(|(line_index, _)| line_index)
+    }
+
+    /// Scroll so that the selected entry and its group header are visible.
+    fn scroll(&mut self, lines: &[Line], height: usize) {
+        let Some(selected_line) = self.selected_line(lines) else {
+            self.offset = 0;
+            return;
+        };
+        let first = match selected_line.checked_sub(1).map(|index| &lines[index]) {
+            Some(Line::Group(_)) => selected_line - 1,
+            _ => selected_line,
+        };
+        if first < self.offset {
+            self.offset = first;
+        } else if height > 0 && selected_line >= self.offset + height {
+            self.offset = selected_line + 1 - height;
+        }
+    }
+
+    /// Copy the current password of the selected entry and return the status
+    /// message.
+    fn copy_selected(&mut self, dirs: &Dirs, tools: &Tools, settings: &Settings) -> String {
+        let Some(index) = self.filtered().get(self.selected).copied() else {
+            return "no entry selected".to_string();
+        };
+        let row = &mut self.rows[index];
+        let totp = row.totp.get_or_insert_with(|| load(dirs, &row.identifier));
+        let result = match totp {
+            Err(error) => Err(Error::Other(error.clone())),
+            Ok(totp) => current_time()
+                .and_then(|time| totp.generate_at(time))
+                .and_then(|code| {
+                    send(&tools.clipboard, &code)?;
+                    match settings.clipboard_timeout {
+                        Some(seconds) => clear_later(tools, &code, seconds),
+                        None => Ok(()),
+                    }
+                }),
+        };
+        match result {
+            Ok(()) => format!("TOTP for {} copied to clipboard.", row.identifier),
+            Err(error) => format!("error: {error}"),
+        }
+    }
+}
+
+/// Additional store of the identifier, if it is in one, and its folder, if
+/// it is in one.
+fn location_of<'a>(dirs: &Dirs, identifier: &'a str) -> (Option<&'a str>, Option<&'a str>) {
+    let (store, name) = match identifier.split_once(STORE_SEPARATOR) {
+        Some((store, name)) if dirs.stores.contains_key(store) => (Some(store), name),
+        _ => (None, identifier),
+    };
+    (store, name.rsplit_once('/').map(|(folder, _)| folder))
+}
+
+/// Group of the location: the folder, qualified with the name of the
+/// additional store, else the name of the store or `default`.
+fn group_of(location: (Option<&str>, Option<&str>)) -> String {
+    match location {
+        (None, None) => DEFAULT_GROUP.to_string(),
+        (Some(store), None) => store.to_string(),
+        (None, Some(folder)) => folder.to_string(),
+        (Some(store), Some(folder)) => format!("{store}{STORE_SEPARATOR}{folder}"),
+    }
+}
+
+/// Decrypt the entry without asking for the passphrase, which would garble
+/// the screen.
+fn load(dirs: &Dirs, identifier: &str) -> Result<Totp, String> {
+    let (totp_dir, name) = dirs.locate(identifier);
+    Store::open(totp_dir, &dirs.gnupg_home)
+        .and_then(|store| store.get_cached(name))
+        .and_then(|entry| entry.totp())
+        .map_err(|error| error.to_string())
+}
+
+fn draw(
+    stdout: &mut Stdout,
+    app: &App,
+    lines: &[Line],
+    width: u16,
+    height: usize,
+) -> Result<(), Error> {
+    let time = current_time()?;
+    let width = usize::from(width);
+    let identifier_width = app
+        .rows
+        .iter()
+        .map(|row| row.identifier.chars().count())
+        .max()
+        .unwrap_or_default();
+    let selected_line = app.selected_line(lines);
+    let clip = |text: String| text.chars().take(width).collect::<String>();
+
+    queue!(
+        stdout,
+        MoveTo(0, 0),
+        SetAttribute(Attribute::Bold),
+        Print(clip(format!("totpc  filter: {}", app.filter))),
+        SetAttribute(Attribute::Reset),
+        Clear(ClearType::UntilNewLine),
+    )
+    .map_err(|err| Error::io("error drawing terminal", err))?;
+    for (row_index, (line_index, line)) in lines
+        .iter()
+        .enumerate()
+        .skip(app.offset)
+        .take(height)
+        .enumerate()
+    {
+        let text = match line {
+            Line::Group(group) => format!("── {group} ──"),
+            Line::Entry(index) => {
+                let row = &app.rows[*index];
+                let marker = if Some(line_index) == selected_line {
+                    ">"
+                } else {
+                    " "
+                };
+                let columns = match &row.totp {
+                    None => "…".to_string(),
+                    Some(Err(error)) => format!("error: {error}"),
+                    Some(Ok(totp)) => match totp.generate_at(time) {
+                        Err(error) => format!("error: {error}"),
+                        Ok(code) => {
+                            let valid_for = totp.valid_for(time);
+                            format!("{code}  {}  {valid_for:>2}s", bar(valid_for, totp.period()))
+                        }
+                    },
+                };
+                format!("{marker} {:identifier_width$}  {columns}", row.identifier)
+            }
+        };
+        let attribute = match line {
+            Line::Group(_) => Attribute::Dim,
+            Line::Entry(_) if Some(line_index) == selected_line => Attribute::Reverse,
+            Line::Entry(_) => Attribute::Reset,
+        };
+        queue!(
+            stdout,
+            MoveTo(0, row_index as u16 + 1),
+            SetAttribute(attribute),
+            Print(clip(text)),
+            SetAttribute(Attribute::Reset),
+            Clear(ClearType::UntilNewLine),
+        )
+        .map_err(|err| Error::io("error drawing terminal", err))?;
+    }
+    // Lines are overwritten instead of clearing the screen, which flickers.
+    queue!(
+        stdout,
+        MoveTo(
+            0,
+            lines.len().saturating_sub(app.offset).min(height) as u16 + 1
+        ),
+        Clear(ClearType::FromCursorDown),
+        MoveTo(0, height as u16 + 1),
+        SetAttribute(Attribute::Dim),
+        Print(clip(app.status.clone())),
+        SetAttribute(Attribute::Reset),
+    )
+    .and_then(|()| stdout.flush())
+    .map_err(|err| Error::io("error drawing terminal", err))
+}
+
+/// Countdown bar of the remaining fraction of the period.
+fn bar(valid_for: u64, period: u64) -> String {
+    let filled = (valid_for * BAR_WIDTH as u64).div_ceil(period.max(1)) as usize;
+    format!(
+        "{}{}",
+        "█".repeat(filled.min(BAR_WIDTH)),
+        "░".repeat(BAR_WIDTH.saturating_sub(filled))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        dirs::Dirs,
+        tui::{bar, group_of, location_of, App, Line, Row, HELP},
+    };
+
+    fn app(identifiers: &[(&str, &str)]) -> App {
+        App {
+            rows: identifiers
+                .iter()
+                .map(|(group, identifier)| Row {
+                    identifier: identifier.to_string(),
+                    group: group.to_string(),
+                    totp: None,
+                })
+                .collect(),
+            filter: String::new(),
+            selected: 0,
+            offset: 0,
+            status: HELP.to_string(),
+        }
+    }
+
+    #[test]
+    fn lines_are_grouped_and_filtered() {
+        let mut app = app(&[
+            ("default", "aws-prod"),
+            ("default", "github"),
+            ("team", "team:aws-root"),
+        ]);
+
+        assert_eq!(
+            app.lines(),
+            vec![
+                Line::Group("default".to_string()),
+                Line::Entry(0),
+                Line::Entry(1),
+                Line::Group("team".to_string()),
+                Line::Entry(2),
+            ]
+        );
+
+        app.set_filter("AWS".to_string());
+        assert_eq!(
+            app.lines(),
+            vec![
+                Line::Group("default".to_string()),
+                Line::Entry(0),
+                Line::Group("team".to_string()),
+                Line::Entry(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn scroll_keeps_selection_visible() {
+        let mut app = app(&[
+            ("default", "a"),
+            ("default", "b"),
+            ("team", "team:c"),
+            ("team", "team:d"),
+        ]);
+        let lines = app.lines();
+
+        app.selected = 3;
+        app.scroll(&lines, 2);
+        assert_eq!(app.offset, 4);
+
+        app.selected = 2;
+        app.scroll(&lines, 2);
+        assert_eq!(app.offset, 3);
+    }
+
+    #[test]
+    fn identifiers_are_grouped_by_store_and_folder() {
+        let mut dirs = Dirs::new("/store", "/gnupg");
+        dirs.stores
+            .insert("team".to_string(), PathBuf::from("/team"));
+        let group = |identifier| group_of(location_of(&dirs, identifier));
+
+        assert_eq!(group("github"), "default");
+        assert_eq!(group("work/aws/prod"), "work/aws");
+        assert_eq!(group("team:aws-root"), "team");
+        assert_eq!(group("team:ops/aws-root"), "team:ops");
+        assert_eq!(group("other:aws"), "default");
+        assert!(location_of(&dirs, "work/github") < location_of(&dirs, "team:aws-root"));
+    }
+
+    #[test]
+    fn bar_shows_remaining_fraction() {
+        assert_eq!(bar(30, 30), "██████████");
+        assert_eq!(bar(15, 30), "█████░░░░░");
+        assert_eq!(bar(1, 30), "█░░░░░░░░░");
+    }
+}
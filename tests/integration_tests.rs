@@ -1,17 +1,21 @@
 const BIN: &str = env!("CARGO_PKG_NAME");
 
 use assert_cmd::prelude::*;
-use std::process::Command;
-use totpc::{ErrorMessage, COMMAND_COMPUTE, COMMAND_DELETE, COMMAND_INIT, COMMAND_SAVE};
+use std::{fs::write, process::Command};
+use tempfile::TempDir;
+use totpc::{
+    COMMAND_CHECK, COMMAND_COMPUTE, COMMAND_DELETE, COMMAND_FIND, COMMAND_INIT, COMMAND_LIST,
+    COMMAND_SAVE, COMMAND_TAG, COMMAND_VERIFY,
+};
 
 #[test]
 fn init_without_id_fails() {
     let mut command = Command::cargo_bin(BIN).unwrap();
     let command = command.arg(COMMAND_INIT);
 
-    command.assert().code(1);
+    command.assert().code(2);
     let error = command.unwrap_err();
-    assert!(error.to_string().contains("gpg id"));
+    assert!(error.to_string().contains("<GPG_ID>"));
 }
 
 #[test]
@@ -19,11 +23,9 @@ fn save_without_identifier_fails() {
     let mut command = Command::cargo_bin(BIN).unwrap();
     let command = command.arg(COMMAND_SAVE);
 
-    command.assert().code(1);
+    command.assert().code(2);
     let error = command.unwrap_err();
-    assert!(error
-        .to_string()
-        .contains(&ErrorMessage::MissingIdentifier(COMMAND_SAVE).to_string()));
+    assert!(error.to_string().contains("<IDENTIFIER>"));
 }
 
 #[test]
@@ -31,11 +33,9 @@ fn read_without_identifier_fails() {
     let mut command = Command::cargo_bin(BIN).unwrap();
     let command = command.arg(COMMAND_DELETE);
 
-    command.assert().code(1);
+    command.assert().code(2);
     let error = command.unwrap_err();
-    assert!(error
-        .to_string()
-        .contains(&ErrorMessage::MissingIdentifier(COMMAND_DELETE).to_string()));
+    assert!(error.to_string().contains("<IDENTIFIER>"));
 }
 
 #[test]
@@ -43,9 +43,208 @@ fn compute_without_identifier_fails() {
     let mut command = Command::cargo_bin(BIN).unwrap();
     let command = command.arg(COMMAND_COMPUTE);
 
-    command.assert().code(1);
+    command.assert().code(2);
+    let error = command.unwrap_err();
+    assert!(error.to_string().contains("<IDENTIFIER>"));
+}
+
+#[test]
+fn verify_without_code_fails() {
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command.arg(COMMAND_VERIFY).arg("identifier");
+
+    command.assert().code(2);
+    let error = command.unwrap_err();
+    assert!(error.to_string().contains("<CODE>"));
+}
+
+#[test]
+fn tag_without_tags_fails() {
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command.arg(COMMAND_TAG).arg("add").arg("identifier");
+
+    command.assert().code(2);
+    let error = command.unwrap_err();
+    assert!(error.to_string().contains("<TAGS>..."));
+}
+
+#[test]
+fn compute_with_extra_argument_fails() {
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command.arg(COMMAND_COMPUTE).arg("identifier").arg("extra");
+
+    command.assert().code(2);
+}
+
+#[test]
+fn compute_without_store_fails_with_not_initialized() {
+    let home_dir = TempDir::new().unwrap();
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", home_dir.path())
+        .arg(COMMAND_COMPUTE)
+        .arg("identifier");
+
+    command.assert().code(3);
+}
+
+#[test]
+fn check_of_uninitialized_store_reports_problems() {
+    let dir = TempDir::new().unwrap();
+    let store_dir = dir.path().join("store");
+    std::fs::create_dir(&store_dir).unwrap();
+    write(store_dir.join("notes.txt"), "").unwrap();
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", dir.path())
+        .arg("--store-dir")
+        .arg(&store_dir)
+        .arg("--format")
+        .arg("json")
+        .arg(COMMAND_CHECK);
+
+    let output = command.output().unwrap();
+
+    assert_eq!(output.status.code(), Some(13));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    let messages = error["problems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|problem| problem["message"].as_str().unwrap())
+        .filter(|message| !message.starts_with("writable"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "missing - run init with a gpg id",
+            "stray file, not a key file"
+        ]
+    );
+}
+
+#[test]
+fn list_includes_qualified_identifiers_of_additional_stores() {
+    let dir = TempDir::new().unwrap();
+    let store_dir = dir.path().join("store");
+    let team_dir = dir.path().join("team");
+    std::fs::create_dir(&store_dir).unwrap();
+    std::fs::create_dir(&team_dir).unwrap();
+    write(store_dir.join("personal.gpg"), "").unwrap();
+    write(team_dir.join("aws-root.gpg"), "").unwrap();
+    let config_file = dir.path().join("config.toml");
+    write(
+        &config_file,
+        format!("[stores]\nteam = {:?}\n", team_dir.to_str().unwrap()),
+    )
+    .unwrap();
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", dir.path())
+        .arg("--config")
+        .arg(&config_file)
+        .arg("--store-dir")
+        .arg(&store_dir)
+        .arg("--format")
+        .arg("raw")
+        .arg(COMMAND_LIST);
+
+    command
+        .assert()
+        .success()
+        .stdout("personal\nteam:aws-root\n");
+}
+
+#[test]
+fn find_outputs_best_matches_first() {
+    let dir = TempDir::new().unwrap();
+    for identifier in ["github", "aws-prod", "prod-aws", "a-w-s"] {
+        write(dir.path().join(format!("{identifier}.gpg")), "").unwrap();
+    }
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", dir.path())
+        .arg("--store-dir")
+        .arg(dir.path())
+        .arg("--format")
+        .arg("raw")
+        .arg(COMMAND_FIND)
+        .arg("AWS");
+
+    command
+        .assert()
+        .success()
+        .stdout("aws-prod\nprod-aws\na-w-s\n");
+}
+
+#[test]
+fn compute_with_ambiguous_identifier_fails_without_terminal() {
+    let dir = TempDir::new().unwrap();
+    for identifier in ["aws-prod", "aws-staging"] {
+        write(dir.path().join(format!("{identifier}.gpg")), "").unwrap();
+    }
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", dir.path())
+        .arg("--store-dir")
+        .arg(dir.path())
+        .arg(COMMAND_COMPUTE)
+        .arg("aws");
+
+    command.assert().code(2);
     let error = command.unwrap_err();
     assert!(error
         .to_string()
-        .contains(&ErrorMessage::MissingIdentifier(COMMAND_COMPUTE).to_string()));
+        .contains("aws matches several identifiers: aws-prod, aws-staging"));
+}
+
+#[test]
+fn compute_all_with_failing_entry_fails_after_output() {
+    let dir = TempDir::new().unwrap();
+    write(dir.path().join(".gpg-id"), "Test Man").unwrap();
+    write(dir.path().join("broken.gpg"), "").unwrap();
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", dir.path())
+        .arg("--store-dir")
+        .arg(dir.path())
+        .arg("--gnupg-home")
+        .arg(dir.path())
+        .arg("--format")
+        .arg("raw")
+        .arg(COMMAND_COMPUTE)
+        .arg("--all");
+
+    command.assert().code(14).stdout("broken\t\n");
+    let error = command.unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("failed to compute 1 of 1 entries"));
+}
+
+#[test]
+fn list_long_with_unreadable_index_decrypts_entries() {
+    let dir = TempDir::new().unwrap();
+    write(dir.path().join(".gpg-id"), "Test Man").unwrap();
+    write(dir.path().join(".index.gpg"), "").unwrap();
+    write(dir.path().join("broken.gpg"), "").unwrap();
+    let mut command = Command::cargo_bin(BIN).unwrap();
+    let command = command
+        .env("HOME", dir.path())
+        .arg("--store-dir")
+        .arg(dir.path())
+        .arg("--gnupg-home")
+        .arg(dir.path())
+        .arg("--format")
+        .arg("json")
+        .arg(COMMAND_LIST)
+        .arg("--long");
+
+    let output = command.output().unwrap();
+
+    assert!(output.status.success());
+    let listing: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listing["identifiers"], serde_json::json!(["broken"]));
+    let warning = &listing["warnings"][0];
+    assert!(warning["path"].as_str().unwrap().ends_with("broken.gpg"));
 }